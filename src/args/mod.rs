@@ -1,6 +1,8 @@
 use std::path::PathBuf;
 
 use clap::Parser;
+use serde::{de::IntoDeserializer, Deserialize};
+use threed_test::config::{Config, IntegratorKind, LoadOptions, OutputFormat, Preset};
 
 /// A N-Body Gravity Simulation Program
 #[derive(Parser, Debug)]
@@ -9,6 +11,129 @@ use clap::Parser;
 pub struct Args {
     #[arg(short, default_value = "config.toml")]
     pub config: PathBuf,
+    /// Run a built-in scenario instead of a config file, example or
+    /// solar-system. Configs can start from one with `preset = "..."`
+    #[arg(long, conflicts_with = "config", value_parser = parse_config_name::<Preset>)]
+    pub preset: Option<Preset>,
+    /// Run without a window, needs --duration, --steps or --replay
+    #[arg(long, requires = "length")]
+    pub headless: bool,
+    /// Simulated time to run a headless simulation for, in the config's time unit
+    #[arg(long, group = "length")]
+    pub duration: Option<f64>,
+    /// Physics ticks to run a headless simulation for
+    #[arg(long, group = "length")]
+    pub steps: Option<u64>,
+    /// Where a headless run writes its final state (as a config),
+    /// stdout if not given
+    #[arg(short, long, requires = "headless")]
+    pub output: Option<PathBuf>,
+    /// Set any config key, like `--set physics.dt="1 h"`, on top of the
+    /// file. Can be given more than once.
+    #[arg(long, value_name = "KEY=VALUE", conflicts_with = "replay", value_parser = parse_override)]
+    pub set: Vec<(String, String)>,
+    /// Seed for everything random in the config, instead of `global.seed`
+    #[arg(
+        long,
+        conflicts_with = "replay",
+        value_parser = clap::value_parser!(u64).range(..=i64::MAX as u64)
+    )]
+    pub seed: Option<u64>,
+    /// Overrides `cheats.time_mult`
+    #[arg(long)]
+    pub time_mult: Option<f64>,
+    /// Overrides `global.window_size`, like 1280x720
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_window_size)]
+    pub window_size: Option<(u32, u32)>,
+    /// Overrides `physics.integrator`
+    #[arg(long, conflicts_with = "replay", value_parser = parse_config_name::<IntegratorKind>)]
+    pub integrator: Option<IntegratorKind>,
+    /// Add the body in a JPL Horizons vector table export, can be given
+    /// more than once
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub import_horizons: Vec<PathBuf>,
+    /// Record trajectories to this file, overrides `output.path`
+    #[arg(long, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// csv, json-lines or binary, guessed from the file's extension if not given
+    #[arg(long, conflicts_with = "replay", value_parser = parse_config_name::<OutputFormat>)]
+    pub record_format: Option<OutputFormat>,
+    /// Simulated time between recorded samples, 0 for every tick
+    #[arg(long, conflicts_with = "replay")]
+    pub record_interval: Option<f64>,
+    /// Record the total energy along with the bodies
+    #[arg(long, conflicts_with = "replay")]
+    pub record_energy: bool,
+    /// Record a replay of the run to this file, overrides `output.replay`
+    #[arg(long, conflicts_with = "replay")]
+    pub record_replay: Option<PathBuf>,
+    /// Play back a replay instead of the config, Space pauses, R reverses
+    /// and the arrow keys step a tick at a time
+    #[arg(long, group = "length")]
+    pub replay: Option<PathBuf>,
+}
+
+impl Args {
+    /// What the config has to be loaded with
+    pub fn load_options(&self) -> LoadOptions {
+        LoadOptions {
+            seed: self.seed,
+            path: self.preset.is_none().then(|| self.config.clone()),
+            imports: self.import_horizons.clone(),
+            overrides: self.set.clone(),
+        }
+    }
+
+    /// Lets flags override what the config file says. Only the ones that
+    /// don't change what happens can be given with `--replay`.
+    pub fn apply_to(&self, config: &mut Config) {
+        if let Some(time_mult) = self.time_mult {
+            config.cheats.time_mult = time_mult;
+        }
+        if let Some(size) = self.window_size {
+            config.global.window_size = Some(size);
+        }
+        if let Some(integrator) = self.integrator {
+            config.physics.integrator = integrator;
+        }
+        if let Some(path) = &self.record {
+            config.output.path = Some(path.clone());
+        }
+        if let Some(format) = self.record_format {
+            config.output.format = Some(format);
+        }
+        if let Some(interval) = self.record_interval {
+            config.output.interval = interval;
+        }
+        config.output.energy |= self.record_energy;
+        if let Some(path) = &self.record_replay {
+            config.output.replay = Some(path.clone());
+        }
+    }
+}
+
+/// Same names as in the config file
+fn parse_config_name<T: for<'de> Deserialize<'de>>(s: &str) -> Result<T, String> {
+    T::deserialize(s.into_deserializer()).map_err(|err: serde::de::value::Error| err.to_string())
+}
+
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {s:?}"))?;
+    Ok((key.trim().to_owned(), value.trim().to_owned()))
+}
+
+fn parse_window_size(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {s:?}"))?;
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|err| format!("{n:?}: {err}"))
+    };
+    Ok((parse(width)?, parse(height)?))
 }
 
 fn get_clap_styles() -> clap::builder::Styles {
@@ -1,8 +1,101 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context as _};
 use three_d::*;
 
-use crate::physics::lighting::ShadowableLight;
+use super::{Dimension, HorizonsVectors, Quantity, UnitSystem};
+use crate::physics::{
+    collision::Collisions,
+    gravity::{Gravity, Solver},
+    integrator::*,
+    orbit::{true_from_mean_anomaly, OrbitalElements},
+    timestep::FixedTimestep,
+    GBody, PhysicsMesh,
+};
+use crate::recorder::{OutputFormat, Recorder};
+use crate::replay::ReplayWriter;
+use crate::render::lighting::ShadowableLight;
+
+impl super::Config {
+    /// Same config with the bodies and clock replaced by the current
+    /// state of `pmesh`, loading it continues from there
+    pub fn with_state(&self, pmesh: &PhysicsMesh) -> Self {
+        let mut config = self.clone();
+        config.bodies = pmesh
+            .components
+            .iter()
+            .map(super::ConfigBody::from_gbody)
+            .collect();
+        config.physics.start_time = pmesh.time;
+        // already moved to it if asked, the bodies are exactly where they are
+        config.physics.barycentric_frame = false;
+        // everything's written in simulation units, `const_g` included
+        config.units = None;
+        // and the preset's and included bodies are in `bodies` now
+        config.preset = None;
+        config.include.clear();
+        // loading it shouldn't record over the files of the run it's from
+        config.output = Default::default();
+        config
+    }
+
+    /// Reads every `import` into `bodies`, leaving it empty. Returns the
+    /// ones that couldn't be by their index in `import`.
+    pub fn take_imports(&mut self) -> Vec<(usize, anyhow::Error)> {
+        let units = self
+            .units
+            .as_ref()
+            .and_then(|units| units.as_unit_system().ok())
+            .unwrap_or_default();
+        let mut first_epoch = None;
+        let mut failed = vec![];
+        for (i, import) in std::mem::take(&mut self.import).into_iter().enumerate() {
+            let body = import.read().and_then(|vectors| {
+                match first_epoch {
+                    None => first_epoch = Some((i, vectors.epoch)),
+                    // a second is plenty for tables exported a bit apart
+                    Some((j, epoch)) if (vectors.epoch - epoch).abs() > 1.0 / 86400.0 => bail!(
+                        "vectors are at JD {} but import[{j}]'s at JD {epoch}, export them at the same time",
+                        vectors.epoch
+                    ),
+                    Some(_) => {}
+                }
+                import.as_body(&vectors, &units, self.global.const_g)
+            });
+            match body {
+                Ok(body) => self.bodies.push(body),
+                Err(err) => failed.push((i, err)),
+            }
+        }
+        failed
+    }
+
+    /// What `[units]` counts time in, for labelling times. Without it
+    /// they're plain numbers like the rest of the config, snapshots
+    /// included as they're written in whatever units they came from.
+    pub fn time_unit(&self) -> Option<&str> {
+        self.units.as_ref().map(|units| units.time.as_str())
+    }
+
+    /// `with_state`, and the camera where it's looking from now
+    pub fn snapshot(&self, pmesh: &PhysicsMesh, camera: &Camera) -> Self {
+        let mut config = self.with_state(pmesh);
+        config.camera = config.camera.with_view(camera);
+        config
+    }
+}
 
 impl super::ConfigCamera {
+    /// Same camera, placed and oriented like `camera`
+    pub fn with_view(self, camera: &Camera) -> Self {
+        Self {
+            position: *camera.position(),
+            target: *camera.target(),
+            up: *camera.up(),
+            ..self
+        }
+    }
+
     pub fn as_camera(self, viewport: Viewport) -> Camera {
         Camera::new_perspective(
             viewport,
@@ -16,13 +109,259 @@ impl super::ConfigCamera {
     }
 }
 
+impl super::ConfigPhysics {
+    pub fn as_gravity(&self, const_g: f64) -> Gravity {
+        let solver = match self.solver {
+            super::SolverKind::Direct => Solver::Direct,
+            super::SolverKind::BarnesHut => Solver::BarnesHut { theta: self.theta },
+        };
+        Gravity::new(const_g, solver)
+            .with_softening(self.softening)
+            .with_threads(self.threads)
+    }
+
+    pub fn as_collisions(&self) -> Collisions {
+        Collisions::new(self.collisions, self.restitution)
+    }
+
+    /// Physics world with `bodies` in it, ready to be stepped, no
+    /// rendering involved
+    pub fn as_physics_mesh(
+        &self,
+        const_g: f64,
+        bodies: Vec<super::ConfigBody>,
+    ) -> anyhow::Result<PhysicsMesh> {
+        let mut pmesh = PhysicsMesh::new(self.as_gravity(const_g), self.as_integrator())
+            .with_collisions(self.as_collisions());
+        super::ConfigBody::as_gbodies(bodies, const_g)?
+            .into_iter()
+            .for_each(|gbody| pmesh.add(gbody));
+        if self.barycentric_frame {
+            pmesh.to_barycentric_frame();
+        }
+        pmesh.time = self.start_time;
+        Ok(pmesh)
+    }
+
+    pub fn as_timestep(&self) -> FixedTimestep {
+        FixedTimestep::new(self.dt, self.max_steps_per_frame)
+    }
+
+    pub fn as_integrator(&self) -> Box<dyn Integrator> {
+        match self.integrator {
+            super::IntegratorKind::Euler => Box::new(SemiImplicitEuler::default()),
+            super::IntegratorKind::VelocityVerlet => Box::new(VelocityVerlet::default()),
+            super::IntegratorKind::Leapfrog => Box::new(LeapfrogKDK::default()),
+            super::IntegratorKind::Yoshida4 => Box::new(Yoshida4::default()),
+            super::IntegratorKind::DormandPrince => Box::new(DormandPrince::new(
+                self.adaptive_tolerance,
+                self.adaptive_max_substeps,
+            )),
+        }
+    }
+}
+
 impl super::ConfigBody {
-    pub fn as_gbody(self, ctx: &Context) -> Result<crate::physics::GBody, three_d_asset::Error> {
-        let mut body =
-            crate::physics::GBody::new(&ctx, self.name, self.color, self.radius, self.mass)?;
-        body.set_motion(self.position, self.velocity);
+    /// `parent` must be the already built body `self.orbit` names, if any
+    pub fn as_gbody(self, const_g: f64, parent: Option<&GBody>) -> anyhow::Result<GBody> {
+        let (mut position, mut velocity) = (self.position, self.velocity);
+        if let Some(orbit) = &self.orbit {
+            let parent = parent
+                .filter(|parent| parent.name == orbit.parent)
+                .ok_or_else(|| anyhow!("{}: parent {:?} not built yet", self.name, orbit.parent))?;
+            let mu = const_g * (parent.mass + self.mass);
+            let (orbit_pos, orbit_vel) = orbit.as_elements()?.to_state_vectors(mu);
+            position += parent.pos + orbit_pos;
+            velocity += parent.vel + orbit_vel;
+        }
+
+        let mut body = GBody::new(self.name, self.color, self.radius, self.mass);
+        body.set_motion(position, velocity);
+        body.softening = self.softening;
+        body.collision = self.collision;
         Ok(body)
     }
+
+    /// Builds all bodies, parents before the bodies orbiting them, and
+    /// returns them in the same order they came in
+    pub fn as_gbodies(bodies: Vec<Self>, const_g: f64) -> anyhow::Result<Vec<GBody>> {
+        let index: HashMap<_, _> = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| (body.name.clone(), i))
+            .collect();
+        let mut pending: Vec<_> = bodies.into_iter().map(Some).collect();
+        let mut built: Vec<Option<GBody>> = pending.iter().map(|_| None).collect();
+
+        for i in 0..pending.len() {
+            // walk up the parents until one that can be built right away
+            let mut chain = vec![i];
+            while let Some(body) = &pending[*chain.last().unwrap()] {
+                let Some(orbit) = &body.orbit else { break };
+                let parent = *index
+                    .get(&orbit.parent)
+                    .ok_or_else(|| anyhow!("{}: no parent body named {:?}", body.name, orbit.parent))?;
+                if chain.contains(&parent) {
+                    bail!("{}: orbits itself through its parents", body.name);
+                }
+                if pending[parent].is_none() {
+                    break;
+                }
+                chain.push(parent);
+            }
+
+            for &j in chain.iter().rev() {
+                let Some(body) = pending[j].take() else { continue };
+                let parent = body
+                    .orbit
+                    .as_ref()
+                    .and_then(|orbit| built[index[&orbit.parent]].as_ref());
+                built[j] = Some(body.as_gbody(const_g, parent)?);
+            }
+        }
+
+        Ok(built.into_iter().flatten().collect())
+    }
+
+    /// Back into config form, current state as plain position and velocity
+    pub fn from_gbody(gbody: &GBody) -> Self {
+        Self {
+            name: gbody.name.clone(),
+            position: gbody.pos,
+            velocity: gbody.vel,
+            color: gbody.color,
+            radius: gbody.radius,
+            mass: gbody.mass,
+            softening: gbody.softening,
+            collision: gbody.collision,
+            orbit: None,
+        }
+    }
+}
+
+impl super::ConfigUnits {
+    pub fn as_unit_system(&self) -> anyhow::Result<UnitSystem> {
+        let base = |key: &str, unit: &str, dimension: Dimension| {
+            let quantity = unit
+                .parse::<Quantity>()
+                .or_else(|_| format!("1 {unit}").parse::<Quantity>())
+                .map_err(|err| anyhow!("units.{key}: {err}"))?;
+            if quantity.dimension != dimension {
+                bail!(
+                    "units.{key}: should be {dimension}, got {}",
+                    quantity.dimension
+                );
+            }
+            Ok(quantity.value)
+        };
+        Ok(UnitSystem {
+            length: base("length", &self.length, Dimension::LENGTH)?,
+            mass: base("mass", &self.mass, Dimension::MASS)?,
+            time: base("time", &self.time, Dimension::TIME)?,
+        })
+    }
+}
+
+impl super::ConfigImport {
+    pub fn read(&self) -> anyhow::Result<HorizonsVectors> {
+        let text = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("can't read {}", self.path.display()))?;
+        HorizonsVectors::parse(&text).with_context(|| self.path.display().to_string())
+    }
+
+    /// `vectors` as read from `path`, in `units`
+    pub fn as_body(
+        &self,
+        vectors: &HorizonsVectors,
+        units: &UnitSystem,
+        const_g: f64,
+    ) -> anyhow::Result<super::ConfigBody> {
+        let convert = |value, dimension| units.to_simulation(Quantity { value, dimension });
+        let name = self
+            .name
+            .clone()
+            .or_else(|| vectors.name.clone())
+            .ok_or_else(|| anyhow!("no target body name in the file, give it a `name`"))?;
+        let mass = match (self.mass, vectors.gm) {
+            (Some(mass), _) => mass,
+            // through G as the simulation has it, so GM comes out exact
+            (None, Some(gm)) => convert(gm, Dimension::GRAVITATIONAL_PARAMETER) / const_g,
+            (None, None) => bail!("no GM in the file to get the mass from, give it a `mass`"),
+        };
+        let radius = self
+            .radius
+            .or_else(|| {
+                vectors
+                    .radius
+                    .map(|radius| convert(radius, Dimension::LENGTH) as f32)
+            })
+            .ok_or_else(|| anyhow!("no mean radius in the file, give it a `radius`"))?;
+
+        Ok(super::ConfigBody {
+            name,
+            position: vectors.position.map(|c| convert(c, Dimension::LENGTH)),
+            velocity: vectors.velocity.map(|c| convert(c, Dimension::VELOCITY)),
+            color: self.color,
+            radius,
+            mass,
+            softening: None,
+            collision: None,
+            orbit: None,
+        })
+    }
+}
+
+impl super::ConfigOutput {
+    pub fn as_recorder(&self) -> anyhow::Result<Option<Recorder>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let format = self
+            .format
+            .or_else(|| OutputFormat::from_path(path))
+            .unwrap_or(OutputFormat::Csv);
+        Recorder::create(path, format, self.interval, self.energy).map(Some)
+    }
+
+    /// `config` is the whole thing the run is started from
+    pub fn as_replay_writer(&self, config: &super::Config) -> anyhow::Result<Option<ReplayWriter>> {
+        self.replay
+            .as_ref()
+            .map(|path| ReplayWriter::create(path, config))
+            .transpose()
+    }
+}
+
+impl super::ConfigOrbit {
+    pub fn as_elements(&self) -> anyhow::Result<OrbitalElements> {
+        let e = self.eccentricity;
+        if e.is_nan() || e < 0.0 || e == 1.0 {
+            bail!("eccentricity must be positive and not exactly 1, got {e}");
+        }
+        if self.semi_major_axis == 0.0 {
+            bail!("semi major axis can't be 0");
+        }
+
+        let true_anomaly = match (self.true_anomaly, self.mean_anomaly) {
+            (Some(_), Some(_)) => bail!("give either true or mean anomaly, not both"),
+            (Some(nu), None) => nu.to_radians(),
+            (None, Some(m)) => true_from_mean_anomaly(m.to_radians(), e),
+            (None, None) => 0.0,
+        };
+
+        Ok(OrbitalElements {
+            semi_major_axis: if e > 1.0 {
+                -self.semi_major_axis.abs()
+            } else {
+                self.semi_major_axis.abs()
+            },
+            eccentricity: e,
+            inclination: self.inclination.to_radians(),
+            longitude_of_ascending_node: self.longitude_of_ascending_node.to_radians(),
+            argument_of_periapsis: self.argument_of_periapsis.to_radians(),
+            true_anomaly,
+        })
+    }
 }
 
 pub trait IntoDynLight {
@@ -30,7 +369,7 @@ pub trait IntoDynLight {
 }
 
 impl super::ConfigLights {
-    pub fn as_scene_lighting(self, ctx: &Context) -> crate::physics::lighting::SceneLights {
+    pub fn as_scene_lighting(self, ctx: &Context) -> crate::render::lighting::SceneLights {
         println!("{self:?}");
         let shadowable_lights = std::iter::empty::<Box<dyn ShadowableLight>>()
             .chain(self.directional.into_iter().map(|l| l.into_dyn_light(ctx)))
@@ -40,7 +379,7 @@ impl super::ConfigLights {
 
         println!("{shadowable_lights:?}");
 
-        crate::physics::lighting::SceneLights {
+        crate::render::lighting::SceneLights {
             lights: shadowable_lights.collect(),
         }
     }
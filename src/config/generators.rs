@@ -0,0 +1,468 @@
+use std::{collections::HashMap, f64::consts::TAU};
+
+use anyhow::{anyhow, bail, ensure};
+use three_d::{InnerSpace, MetricSpace, Vector3, Zero};
+
+use super::{rng::Rng, ConfigBody, ConfigGenerator, GeneratorKind, MassDistribution};
+use crate::physics::orbit::{true_from_mean_anomaly, OrbitalElements};
+
+/// Plummer spheres have no edge, bodies further out than this many
+/// scale radii are picked again
+const PLUMMER_CUTOFF: f64 = 10.0;
+
+/// Times a body is placed again for overlapping ones already there
+/// before giving up on fitting them all
+const PLACEMENT_ATTEMPTS: u32 = 100;
+
+impl GeneratorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Disk { .. } => "disk",
+            Self::Ring { .. } => "ring",
+            Self::Plummer { .. } => "plummer",
+            Self::Binaries { .. } => "binaries",
+        }
+    }
+}
+
+impl ConfigGenerator {
+    /// The bodies this makes, `bodies` being the ones already there to
+    /// find the center in. `rng` is only used without a `seed`.
+    pub(super) fn generate(
+        &self,
+        bodies: &[ConfigBody],
+        const_g: f64,
+        rng: Rng,
+    ) -> anyhow::Result<Vec<ConfigBody>> {
+        let range_ok = |(min, max): (f64, f64)| min > 0.0 && min <= max && max.is_finite();
+        ensure!(
+            range_ok(self.mass),
+            "mass must be [min, max] above 0, got {:?}",
+            self.mass
+        );
+        ensure!(
+            range_ok(self.body_radius),
+            "body_radius must be [min, max] above 0, got {:?}",
+            self.body_radius
+        );
+
+        let mut rng = self.seed.map(Rng::new).unwrap_or(rng);
+        match &self.kind {
+            GeneratorKind::Disk {
+                center,
+                inner_radius,
+                outer_radius,
+                thickness,
+                normal,
+            } => {
+                ensure!(*thickness >= 0.0, "thickness can't be negative");
+                let orbits = Orbits::around(center, bodies, const_g, *normal)?;
+                self.circular(
+                    &mut rng,
+                    orbits,
+                    (*inner_radius, *outer_radius),
+                    |rng, r| (rng.uniform(r.0, r.1), rng.normal(0.0, *thickness)),
+                )
+            }
+            GeneratorKind::Ring {
+                center,
+                inner_radius,
+                outer_radius,
+                normal,
+            } => {
+                let orbits = Orbits::around(center, bodies, const_g, *normal)?;
+                self.circular(
+                    &mut rng,
+                    orbits,
+                    (*inner_radius, *outer_radius),
+                    |rng, r| {
+                        // evenly over the area, not the radius
+                        (rng.uniform(r.0 * r.0, r.1 * r.1).sqrt(), 0.0)
+                    },
+                )
+            }
+            GeneratorKind::Plummer {
+                scale_radius,
+                position,
+                velocity,
+            } => self.plummer(&mut rng, const_g, *scale_radius, *position, *velocity),
+            GeneratorKind::Binaries {
+                region_radius,
+                separation,
+                eccentricity,
+                position,
+                velocity,
+            } => {
+                ensure!(*region_radius >= 0.0, "region_radius can't be negative");
+                ensure!(
+                    range_ok(*separation),
+                    "separation must be [min, max] above 0, got {separation:?}"
+                );
+                let (min_e, max_e) = *eccentricity;
+                ensure!(
+                    min_e >= 0.0 && min_e <= max_e && max_e < 1.0,
+                    "eccentricity must be [min, max] from 0 to under 1, got {eccentricity:?}"
+                );
+                let mut bodies = self.binaries(
+                    &mut rng,
+                    const_g,
+                    *region_radius,
+                    *separation,
+                    *eccentricity,
+                )?;
+                for body in &mut bodies {
+                    body.position += *position;
+                    body.velocity += *velocity;
+                }
+                Ok(bodies)
+            }
+        }
+    }
+
+    /// Body number `i`, not placed yet
+    fn body(&self, rng: &mut Rng, i: impl std::fmt::Display) -> ConfigBody {
+        let (min, max) = self.mass;
+        let mass = match self.mass_distribution {
+            MassDistribution::Uniform => rng.uniform(min, max),
+            MassDistribution::LogUniform => rng.uniform(min.ln(), max.ln()).exp(),
+            MassDistribution::Salpeter => {
+                // inverse of the cumulative distribution of m^-2.35
+                let power = 1.0 - 2.35;
+                rng.uniform(min.powf(power), max.powf(power))
+                    .powf(1.0 / power)
+            }
+        };
+        let name = self.name.as_deref().unwrap_or(self.kind.name());
+
+        ConfigBody {
+            name: format!("{name}-{i}"),
+            position: Vector3::zero(),
+            velocity: Vector3::zero(),
+            color: self.color,
+            radius: rng.uniform(self.body_radius.0, self.body_radius.1) as f32,
+            mass,
+            softening: None,
+            collision: None,
+            orbit: None,
+        }
+    }
+
+    /// Circular orbits in `orbits`' plane, `pick` giving each one's radius
+    /// and height over the plane from `radii`
+    fn circular(
+        &self,
+        rng: &mut Rng,
+        orbits: Orbits,
+        radii: (f64, f64),
+        pick: impl Fn(&mut Rng, (f64, f64)) -> (f64, f64),
+    ) -> anyhow::Result<Vec<ConfigBody>> {
+        ensure!(
+            radii.0 > 0.0 && radii.0 < radii.1,
+            "inner_radius must be above 0 and under outer_radius, got {} and {}",
+            radii.0,
+            radii.1
+        );
+
+        let mut bodies = vec![];
+        let mut spacing = Spacing::new(self.body_radius.1);
+        for i in 0..self.count {
+            let mut body = self.body(rng, i);
+            let (r, tangent) = spacing.place(&mut body, || {
+                let (r, height) = pick(rng, radii);
+                let angle = TAU * rng.next_f64();
+                let (sin, cos) = angle.sin_cos();
+                let radial = orbits.x * cos + orbits.y * sin;
+                let tangent = orbits.y * cos - orbits.x * sin;
+                let position = orbits.position + radial * r + orbits.up * height;
+                (position, (r, tangent))
+            })?;
+            let speed = (orbits.const_g * (orbits.mass + body.mass) / r).sqrt();
+            body.velocity = orbits.velocity + tangent * speed;
+            bodies.push(body);
+        }
+        Ok(bodies)
+    }
+
+    /// Positions from the Plummer density, velocities from its
+    /// distribution function by rejection (Aarseth, Hénon & Wielen 1974)
+    fn plummer(
+        &self,
+        rng: &mut Rng,
+        const_g: f64,
+        scale_radius: f64,
+        position: Vector3<f64>,
+        velocity: Vector3<f64>,
+    ) -> anyhow::Result<Vec<ConfigBody>> {
+        ensure!(scale_radius > 0.0, "scale_radius must be above 0");
+        let mut bodies: Vec<_> = (0..self.count).map(|i| self.body(rng, i)).collect();
+        let total_mass: f64 = bodies.iter().map(|body| body.mass).sum();
+
+        let mut spacing = Spacing::new(self.body_radius.1);
+        for body in &mut bodies {
+            let r = spacing.place(body, || {
+                let r = loop {
+                    // radius holding a random fraction of the mass
+                    let fraction = rng.next_f64();
+                    let r = scale_radius / (fraction.powf(-2.0 / 3.0) - 1.0).sqrt();
+                    if r < PLUMMER_CUTOFF * scale_radius {
+                        break r;
+                    }
+                };
+                (rng.direction() * r, r)
+            })?;
+            let q = loop {
+                let q = rng.next_f64();
+                if 0.1 * rng.next_f64() < q * q * (1.0 - q * q).powf(3.5) {
+                    break q;
+                }
+            };
+            let escape_speed =
+                (2.0 * const_g * total_mass / (r * r + scale_radius * scale_radius).sqrt()).sqrt();
+            body.velocity = rng.direction() * q * escape_speed;
+        }
+
+        // so it's exactly at `position`, moving at `velocity`
+        let weighted = |v: fn(&ConfigBody) -> Vector3<f64>| {
+            bodies
+                .iter()
+                .map(|body| v(body) * body.mass)
+                .fold(Vector3::zero(), |a, b| a + b)
+                / total_mass
+        };
+        let (center, drift) = (weighted(|b| b.position), weighted(|b| b.velocity));
+        for body in &mut bodies {
+            body.position += position - center;
+            body.velocity += velocity - drift;
+        }
+        Ok(bodies)
+    }
+
+    /// Around the origin, at rest
+    fn binaries(
+        &self,
+        rng: &mut Rng,
+        const_g: f64,
+        region_radius: f64,
+        separation: (f64, f64),
+        eccentricity: (f64, f64),
+    ) -> anyhow::Result<Vec<ConfigBody>> {
+        let mut bodies = vec![];
+        let mut spacing = Spacing::new(self.body_radius.1);
+        for i in 0..self.count {
+            let mut a = self.body(rng, format!("{i}a"));
+            let mut b = self.body(rng, format!("{i}b"));
+            let total_mass = a.mass + b.mass;
+
+            let mut attempts = 0;
+            loop {
+                let barycenter = rng.direction() * region_radius * rng.next_f64().cbrt();
+                let e = rng.uniform(eccentricity.0, eccentricity.1);
+                let elements = OrbitalElements {
+                    semi_major_axis: rng.uniform(separation.0.ln(), separation.1.ln()).exp(),
+                    eccentricity: e,
+                    // evenly oriented planes
+                    inclination: rng.uniform(-1.0, 1.0).acos(),
+                    longitude_of_ascending_node: TAU * rng.next_f64(),
+                    argument_of_periapsis: TAU * rng.next_f64(),
+                    true_anomaly: true_from_mean_anomaly(TAU * rng.next_f64(), e),
+                };
+                let (pos, vel) = elements.to_state_vectors(const_g * total_mass);
+
+                a.position = barycenter - pos * (b.mass / total_mass);
+                a.velocity = -vel * (b.mass / total_mass);
+                b.position = barycenter + pos * (a.mass / total_mass);
+                b.velocity = vel * (a.mass / total_mass);
+                let apart = a.position.distance(b.position) >= (a.radius + b.radius) as f64;
+                if apart && spacing.fits(&a) && spacing.fits(&b) {
+                    break;
+                }
+                attempts += 1;
+                if attempts == PLACEMENT_ATTEMPTS {
+                    bail!(Spacing::too_crowded(&b));
+                }
+            }
+            spacing.insert(&a);
+            spacing.insert(&b);
+            bodies.extend([a, b]);
+        }
+        Ok(bodies)
+    }
+}
+
+/// Generated bodies placed so far, bucketed into cubes as wide as the
+/// biggest pair of radii so only neighbouring cubes can hold ones that
+/// overlap
+struct Spacing {
+    cell: f64,
+    placed: HashMap<[i64; 3], Vec<(Vector3<f64>, f64)>>,
+}
+
+impl Spacing {
+    fn new(max_radius: f64) -> Self {
+        Self {
+            cell: 2.0 * max_radius,
+            placed: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, position: Vector3<f64>) -> [i64; 3] {
+        [position.x, position.y, position.z].map(|c| (c / self.cell).floor() as i64)
+    }
+
+    /// Whether `body` is clear of every body placed so far
+    fn fits(&self, body: &ConfigBody) -> bool {
+        let [x, y, z] = self.cell_of(body.position);
+        let radius = body.radius as f64;
+        (-1..=1)
+            .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [dx, dy, dz])))
+            .filter_map(|[dx, dy, dz]| self.placed.get(&[x + dx, y + dy, z + dz]))
+            .flatten()
+            .all(|(position, other)| body.position.distance(*position) >= radius + other)
+    }
+
+    fn insert(&mut self, body: &ConfigBody) {
+        let cell = self.cell_of(body.position);
+        let entry = (body.position, body.radius as f64);
+        self.placed.entry(cell).or_default().push(entry);
+    }
+
+    /// Moves `body` to positions from `pick` until it's clear of the
+    /// others, returning whatever `pick` gave with the one that was
+    fn place<T>(
+        &mut self,
+        body: &mut ConfigBody,
+        mut pick: impl FnMut() -> (Vector3<f64>, T),
+    ) -> anyhow::Result<T> {
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let (position, picked) = pick();
+            body.position = position;
+            if self.fits(body) {
+                self.insert(body);
+                return Ok(picked);
+            }
+        }
+        bail!(Self::too_crowded(body))
+    }
+
+    fn too_crowded(body: &ConfigBody) -> String {
+        format!(
+            "couldn't find room for {:?} clear of the others after {PLACEMENT_ATTEMPTS} tries, \
+             the bodies need to be smaller or spread over more space",
+            body.name
+        )
+    }
+}
+
+/// Where circular orbits around some body are, and in which plane
+struct Orbits {
+    position: Vector3<f64>,
+    velocity: Vector3<f64>,
+    mass: f64,
+    const_g: f64,
+    up: Vector3<f64>,
+    /// Any two directions in the plane, square to each other
+    x: Vector3<f64>,
+    y: Vector3<f64>,
+}
+
+impl Orbits {
+    fn around(
+        center: &str,
+        bodies: &[ConfigBody],
+        const_g: f64,
+        normal: Vector3<f64>,
+    ) -> anyhow::Result<Self> {
+        ensure!(normal.magnitude2() > 0.0, "normal can't be 0");
+        let built = ConfigBody::as_gbodies(bodies.to_vec(), const_g)?;
+        let center = built
+            .into_iter()
+            .find(|gbody| gbody.name == center)
+            .ok_or_else(|| anyhow!("no body named {center:?} to be the center"))?;
+
+        let up = normal.normalize();
+        let not_up = if up.x.abs() < 0.9 {
+            Vector3::unit_x()
+        } else {
+            Vector3::unit_y()
+        };
+        let x = (not_up - up * up.dot(not_up)).normalize();
+        Ok(Self {
+            position: center.pos,
+            velocity: center.vel,
+            mass: center.mass,
+            const_g,
+            up,
+            x,
+            y: up.cross(x),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::Config, physics::collision::touching_pairs};
+
+    fn generated(generator: &str) -> Config {
+        let source = format!(
+            r#"
+            [physics]
+            collisions = "bounce"
+
+            [[bodies]]
+            name = "center"
+            position = [0, 0, 0]
+            velocity = [0, 0, 0]
+            color = [255, 255, 255, 255]
+            radius = 1.0
+            mass = 1e6
+
+            [[generators]]
+            seed = 1
+            mass = [1, 2]
+            body_radius = [0.05, 0.1]
+            {generator}
+            "#
+        );
+        Config::from_toml(&source, "test").unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    #[test]
+    fn places_bodies_clear_of_each_other() {
+        for generator in [
+            r#"kind = "ring"
+            count = 3000
+            center = "center"
+            inner_radius = 5
+            outer_radius = 15"#,
+            r#"kind = "plummer"
+            count = 2000
+            scale_radius = 10
+            position = [100, 0, 0]"#,
+            r#"kind = "binaries"
+            count = 500
+            region_radius = 20
+            separation = [0.15, 1]
+            position = [-100, 0, 0]"#,
+        ] {
+            let config = generated(generator);
+            let gbodies =
+                crate::config::ConfigBody::as_gbodies(config.bodies, config.global.const_g)
+                    .unwrap();
+            assert!(touching_pairs(&gbodies).is_empty(), "{generator}");
+        }
+    }
+
+    #[test]
+    fn gives_up_when_they_cant_fit() {
+        let source = r#"
+            [[generators]]
+            kind = "plummer"
+            count = 100
+            scale_radius = 0.01
+            mass = [1, 2]
+            body_radius = [1, 1]
+            "#;
+        let err = Config::from_toml(source, "test").unwrap_err().to_string();
+        assert!(err.contains("couldn't find room"), "{err}");
+    }
+}
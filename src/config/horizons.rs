@@ -0,0 +1,243 @@
+use anyhow::{anyhow, bail, Context as _};
+use three_d::Vector3;
+
+/// The first state vectors in a JPL Horizons vector table export
+/// (`EPHEM_TYPE=VECTORS`, plain or `CSV_FORMAT=YES`), in SI
+#[derive(Debug, Clone)]
+pub struct HorizonsVectors {
+    /// `Target body name`, without the id
+    pub name: Option<String>,
+    /// Julian day (TDB) the vectors are at
+    pub epoch: f64,
+    /// Relative to whatever center the table was exported around
+    pub position: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+    /// From the physical data in the header, when there is any
+    pub gm: Option<f64>,
+    pub radius: Option<f64>,
+}
+
+const COMPONENTS: [&str; 6] = ["X", "Y", "Z", "VX", "VY", "VZ"];
+
+impl HorizonsVectors {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let (header, rest) = text
+            .split_once("$$SOE")
+            .ok_or_else(|| anyhow!("no $$SOE, not a Horizons vector table"))?;
+        let (table, _) = rest
+            .split_once("$$EOE")
+            .ok_or_else(|| anyhow!("no $$EOE after $$SOE"))?;
+
+        let (length, time) = match header_field(header, "Output units") {
+            // the default when it isn't said
+            None => (1e3, 1.0),
+            Some(units) => match units.split_whitespace().next() {
+                Some("KM-S") => (1e3, 1.0),
+                Some("KM-D") => (1e3, 86400.0),
+                Some("AU-D") => (1.495_978_707e11, 86400.0),
+                _ => bail!("unknown output units {units:?}, export in KM-S, KM-D or AU-D"),
+            },
+        };
+
+        let first = table.lines().find(|line| !line.trim().is_empty());
+        let (epoch, values) = match first {
+            Some(line) if line.contains(',') => csv_record(header, line)?,
+            _ => text_record(table)?,
+        };
+        let vector = |[x, y, z]: [f64; 3], scale: f64| Vector3::new(x, y, z) * scale;
+
+        Ok(Self {
+            name: header_field(header, "Target body name").map(|name| {
+                // "Earth (399)   {source: DE441}"
+                let name = name.split("  ").next().unwrap_or(name);
+                let name = name.split('{').next().unwrap_or(name).trim();
+                match name.rsplit_once(" (") {
+                    Some((name, id)) if id.ends_with(')') => name.to_owned(),
+                    _ => name.to_owned(),
+                }
+            }),
+            epoch,
+            position: vector([values[0], values[1], values[2]], length),
+            velocity: vector([values[3], values[4], values[5]], length / time),
+            gm: header_number(header, &["GM, km^3/s^2", "GM (km^3/s^2)", "GM, km³/s²"])
+                .map(|gm| gm * 1e9),
+            radius: header_number(
+                header,
+                &[
+                    "Vol. Mean Radius (km)",
+                    "Vol. mean radius (km)",
+                    "Vol. mean radius, km",
+                    "Mean radius (km)",
+                ],
+            )
+            .map(|radius| radius * 1e3),
+        })
+    }
+}
+
+/// Rest of the line after `"{name}:"`
+fn header_field<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == name).then(|| value.trim())
+    })
+}
+
+/// Number after the first of `labels` and an `=`, physical data is laid
+/// out in two columns of `label = value`
+fn header_number(header: &str, labels: &[&str]) -> Option<f64> {
+    labels.iter().find_map(|label| {
+        let after = &header[header.find(label)? + label.len()..];
+        let after = after.trim_start().strip_prefix('=')?.trim_start();
+        let end = after
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(after.len());
+        // uncertainties are written right after, like "6371.01+-0.02"
+        let number = after[..end].split("+-").next()?;
+        number.parse().ok()
+    })
+}
+
+/// `2451545.0 = A.D. 2000-Jan-01 12:00:00.0000 TDB` and then the
+/// components as `X =-1.7E-01 Y = 9.6E-01 ...`
+fn text_record(table: &str) -> anyhow::Result<(f64, [f64; 6])> {
+    let spaced = table.replace('=', " = ");
+    let tokens: Vec<_> = spaced.split_whitespace().collect();
+    let epoch = tokens
+        .first()
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| anyhow!("no julian day starting the first record"))?;
+
+    let mut values = [0.0; 6];
+    for (value, component) in values.iter_mut().zip(COMPONENTS) {
+        // the first of each belongs to the first record
+        let at = tokens
+            .windows(3)
+            .position(|window| window[0] == component && window[1] == "=")
+            .ok_or_else(|| anyhow!("no {component} in the first record, export with velocities"))?;
+        *value = tokens[at + 2]
+            .parse()
+            .with_context(|| format!("bad {component} {:?}", tokens[at + 2]))?;
+    }
+    Ok((epoch, values))
+}
+
+/// `2451545.0, A.D. 2000-Jan-01 12:00:00.0000, -1.7E-01, ...`, columns
+/// named by the last line of the header that names them all
+fn csv_record(header: &str, line: &str) -> anyhow::Result<(f64, [f64; 6])> {
+    let columns = header
+        .lines()
+        .rev()
+        .map(split_csv)
+        .find(|columns| COMPONENTS.iter().all(|c| columns.contains(c)))
+        .ok_or_else(|| anyhow!("no X, Y, Z, VX, VY, VZ columns, export with velocities"))?;
+    let fields = split_csv(line);
+    let field = |name: &str| -> anyhow::Result<f64> {
+        let i = columns.iter().position(|column| *column == name).unwrap();
+        let field = fields
+            .get(i)
+            .ok_or_else(|| anyhow!("first record has no {name} column"))?;
+        field
+            .parse()
+            .with_context(|| format!("bad {name} {field:?}"))
+    };
+
+    let epoch = fields
+        .first()
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| anyhow!("no julian day starting the first record"))?;
+    let mut values = [0.0; 6];
+    for (value, component) in values.iter_mut().zip(COMPONENTS) {
+        *value = field(component)?;
+    }
+    Ok((epoch, values))
+}
+
+fn split_csv(line: &str) -> Vec<&str> {
+    line.split(',').map(str::trim).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AU: f64 = 1.495_978_707e11;
+
+    const PLAIN: &str = "\
+ GEOPHYSICAL PROPERTIES (revised May 9, 2022):
+  Vol. Mean Radius (km)    = 6371.01+-0.02   Mass x10^24 (kg)= 5.97219+-0.0006
+  GM, km^3/s^2             = 398600.435436   Inner core rad  = 1215 km
+*******************************************************************************
+Target body name: Earth (399)                     {source: DE441}
+Center body name: Sun (10)                        {source: DE441}
+Output units    : AU-D
+*******************************************************************************
+$$SOE
+2451545.000000000 = A.D. 2000-Jan-01 12:00:00.0000 TDB 
+ X =-1.771350992727098E-01 Y = 9.672416867665306E-01 Z =-4.085281582511366E-06
+ VX=-1.720762506872895E-02 VY=-3.158782144324866E-03 VZ= 1.049888594613343E-06
+ LT= 5.674220808746849E-03 RG= 9.824681249603316E-01 RR=-1.086677248292449E-05
+2451546.000000000 = A.D. 2000-Jan-02 12:00:00.0000 TDB 
+ X =-1.943185758672232E-01 Y = 9.638781739850682E-01 Z =-3.015399725986002E-06
+ VX=-1.716766094939798E-02 VY=-3.563547098113209E-03 VZ= 1.076035542713938E-06
+$$EOE
+";
+
+    const CSV: &str = "\
+ PHYSICAL PROPERTIES (updated 2018-Aug-15):
+  GM, km^3/s^2          = 4902.800118         Density, g/cm^3       = 3.34
+  Vol. mean radius, km  = 1737.4              Volume, 10^12 km^3    = 21.958
+Target body name: Moon (301)                      {source: DE441}
+Output units    : KM-S
+*******************************************************************************
+            JDTDB,            Calendar Date (TDB),                      X,                      Y,                      Z,                     VX,                     VY,                     VZ,
+*******************************************************************************
+$$SOE
+2451545.000000000, A.D. 2000-Jan-01 12:00:00.0000, -2.916083884571521E+05, -2.667168292374620E+05,  7.610248595209483E+03,  6.435313736079164E-01, -6.660876955662817E-01, -1.300959006046617E-02,
+2451546.000000000, A.D. 2000-Jan-02 12:00:00.0000, -2.330601006473016E+05, -3.183468216318153E+05,  6.409474104830949E+03,  7.109196218208097E-01, -5.277232700698547E-01, -1.471087389706697E-02,
+$$EOE
+";
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-12 * a.abs().max(b.abs())
+    }
+
+    #[test]
+    fn parses_plain_text_tables() {
+        let vectors = HorizonsVectors::parse(PLAIN).unwrap();
+        assert_eq!(vectors.name.as_deref(), Some("Earth"));
+        assert_eq!(vectors.epoch, 2451545.0);
+        assert!(close(vectors.position.x, -1.771350992727098e-1 * AU));
+        assert!(close(vectors.position.y, 9.672416867665306e-1 * AU));
+        assert!(close(
+            vectors.velocity.x,
+            -1.720762506872895e-2 * AU / 86400.0
+        ));
+        assert!(close(
+            vectors.velocity.z,
+            1.049888594613343e-6 * AU / 86400.0
+        ));
+        assert_eq!(vectors.gm, Some(398600.435436e9));
+        assert_eq!(vectors.radius, Some(6371.01e3));
+    }
+
+    #[test]
+    fn parses_csv_tables() {
+        let vectors = HorizonsVectors::parse(CSV).unwrap();
+        assert_eq!(vectors.name.as_deref(), Some("Moon"));
+        assert_eq!(vectors.epoch, 2451545.0);
+        assert!(close(vectors.position.x, -2.916083884571521e8));
+        assert!(close(vectors.position.z, 7.610248595209483e6));
+        assert!(close(vectors.velocity.y, -6.660876955662817e2));
+        assert_eq!(vectors.gm, Some(4902.800118e9));
+        assert_eq!(vectors.radius, Some(1737.4e3));
+    }
+
+    #[test]
+    fn rejects_other_tables() {
+        assert!(HorizonsVectors::parse("no table here").is_err());
+        let other_units = PLAIN.replace("AU-D", "AU-S");
+        let err = HorizonsVectors::parse(&other_units).unwrap_err();
+        assert!(err.to_string().contains("unknown output units"), "{err}");
+    }
+}
@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+
+use super::{
+    merge::merge,
+    random,
+    rng::Rng,
+    units::{self, UnitSystem},
+    validate::{unknown_keys, ConfigErrors, KeyPath, Problem},
+    Config,
+};
+
+/// Everything `doc` includes merged in order, for `doc` to go on top of.
+/// Paths are relative to `dir`, the folder `doc` is in, and `chain` is
+/// every file that led here so a file can't end up including itself.
+pub(super) fn included(
+    doc: &toml_edit::ImDocument<&str>,
+    origin: &str,
+    dir: &Path,
+    units: &UnitSystem,
+    seed: u64,
+    chain: &[PathBuf],
+) -> Result<toml::Table, ConfigErrors> {
+    let fail = |path: KeyPath, message: String| {
+        let problem = Problem {
+            span: path.span_in(doc),
+            path,
+            message,
+        };
+        ConfigErrors::new(origin, Some(doc.raw()), vec![problem])
+    };
+
+    let mut table = toml::Table::new();
+    let Some(include) = doc.get("include").and_then(|include| include.as_array()) else {
+        return Ok(table);
+    };
+    for (i, entry) in include.iter().enumerate() {
+        let path = KeyPath::default().key("include").index(i);
+        // not being a string is caught when the whole config is read
+        let Some(entry) = entry.as_str() else {
+            continue;
+        };
+        let file = dir.join(entry);
+        let source = std::fs::read_to_string(&file).map_err(|err| {
+            fail(
+                path.clone(),
+                format!("can't read {}: {err}", file.display()),
+            )
+        })?;
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if chain.contains(&canonical) {
+            return Err(fail(
+                path,
+                format!("{} ends up including itself", file.display()),
+            ));
+        }
+
+        // so the same paths in different files don't pick the same numbers
+        let seed = Rng::derived(seed, &path.to_string()).next_u64();
+        let mut chain = chain.to_vec();
+        chain.push(canonical);
+        let file_dir = file.parent().unwrap_or(Path::new(""));
+        let origin = file.display().to_string();
+        merge(
+            &mut table,
+            file_table(&source, &origin, file_dir, units, seed, &chain)?,
+        );
+    }
+    Ok(table)
+}
+
+/// An included file (or anything else going on top of a config) with
+/// its own includes under it, quantities in `units` like the config
+/// including it
+pub(super) fn file_table(
+    source: &str,
+    origin: &str,
+    dir: &Path,
+    units: &UnitSystem,
+    seed: u64,
+    chain: &[PathBuf],
+) -> Result<toml::Table, ConfigErrors> {
+    let fail = |problems| ConfigErrors::new(origin, Some(source), problems);
+    let doc = toml_edit::ImDocument::parse(source).map_err(|err| {
+        fail(vec![Problem {
+            path: KeyPath::default(),
+            message: err.message().to_owned(),
+            span: err.span(),
+        }])
+    })?;
+
+    let mut problems = vec![];
+    let root = KeyPath::default();
+    let own_seed = doc.get("global").and_then(|global| global.get("seed"));
+    for (path, item) in [
+        (root.key("units"), doc.get("units")),
+        (root.key("preset"), doc.get("preset")),
+        // what's random in here was already picked with the loaded one's
+        (root.key("global").key("seed"), own_seed),
+    ] {
+        if let Some(item) = item {
+            problems.push(Problem {
+                path,
+                message: "only the config that's loaded can set this".to_owned(),
+                span: item.span(),
+            });
+        }
+    }
+    let mut replacements = units::convert(&doc, units, &mut problems);
+    replacements.extend(random::resolve(&doc, units, seed, &mut problems));
+    let converted = units::splice(source, replacements);
+    if !problems.is_empty() {
+        return Err(fail(problems));
+    }
+
+    let config: Config = toml::from_str(&converted).map_err(|err| {
+        fail(vec![Problem {
+            path: KeyPath::default(),
+            message: err.message().to_owned(),
+            span: err.span(),
+        }])
+    })?;
+    if let Ok(known) = toml::Value::try_from(&config) {
+        unknown_keys(doc.as_item(), &known, &KeyPath::default(), &mut problems);
+    }
+    if !problems.is_empty() {
+        return Err(fail(problems));
+    }
+
+    let mut table = included(&doc, origin, dir, units, seed, chain)?;
+    let mut file: toml::Table = toml::from_str(&converted).expect("parsed as a config just above");
+    file.remove("include");
+    resolve_imports(&mut file, dir);
+    merge(&mut table, file);
+    Ok(table)
+}
+
+/// Makes the `import` paths of `table`, a file in `dir`, relative to
+/// where the program runs instead, for them to still point to the same
+/// files once merged with ones from somewhere else
+pub(super) fn resolve_imports(table: &mut toml::Table, dir: &Path) {
+    let Some(imports) = table
+        .get_mut("import")
+        .and_then(|imports| imports.as_array_mut())
+    else {
+        return;
+    };
+    for import in imports {
+        if let Some(toml::Value::String(path)) = import.get_mut("path") {
+            *path = dir.join(&*path).display().to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_imports_against_their_file() {
+        let mut table: toml::Table = r#"
+            [[import]]
+            path = "earth.txt"
+            [[import]]
+            path = "/horizons/moon.txt"
+            "#
+        .parse()
+        .unwrap();
+        resolve_imports(&mut table, Path::new("configs/solar"));
+
+        let paths: Vec<_> = table["import"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|import| PathBuf::from(import["path"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("configs/solar/earth.txt"),
+                Path::new("/horizons/moon.txt")
+            ]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use toml::{Table, Value};
+
+/// How many elements each array of tables already had before `merge`
+/// added more, by path like `bodies` or `lights.point`
+pub(super) type Offsets = HashMap<String, usize>;
+
+/// Puts `over` on top of `base`. Tables are merged key by key, arrays of
+/// tables (`bodies`, `lights.*`) are concatenated and anything else in
+/// `over` replaces what `base` had.
+pub(super) fn merge(base: &mut Table, over: Table) -> Offsets {
+    let mut offsets = Offsets::new();
+    merge_at("", base, over, &mut offsets);
+    offsets
+}
+
+fn merge_at(path: &str, base: &mut Table, over: Table, offsets: &mut Offsets) {
+    let is_tables = |array: &[Value]| array.iter().all(Value::is_table);
+    for (key, value) in over {
+        let path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{path}.{key}")
+        };
+        let value = match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(over)) => {
+                merge_at(&path, base, over, offsets);
+                continue;
+            }
+            (Some(Value::Array(base)), Value::Array(over))
+                if is_tables(base) && is_tables(&over) =>
+            {
+                offsets.insert(path, base.len());
+                base.extend(over);
+                continue;
+            }
+            (_, value) => value,
+        };
+        base.insert(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(source: &str) -> Table {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn concatenates_arrays_of_tables() {
+        let mut base = table(
+            r#"
+            [[bodies]]
+            name = "sun"
+            [[lights.point]]
+            intensity = 1.0
+            "#,
+        );
+        let offsets = merge(
+            &mut base,
+            table(
+                r#"
+                [[bodies]]
+                name = "earth"
+                [[bodies]]
+                name = "moon"
+                [[lights.point]]
+                intensity = 0.5
+                "#,
+            ),
+        );
+
+        let names: Vec<_> = base["bodies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|body| body["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["sun", "earth", "moon"]);
+        assert_eq!(base["lights"]["point"].as_array().unwrap().len(), 2);
+        assert_eq!(offsets["bodies"], 1);
+        assert_eq!(offsets["lights.point"], 1);
+    }
+
+    #[test]
+    fn overrides_everything_else() {
+        let mut base = table(
+            r#"
+            [global]
+            window_name = "base"
+            const_g = 1.0
+            [camera]
+            position = [1, 2, 3]
+            "#,
+        );
+        let offsets = merge(
+            &mut base,
+            table(
+                r#"
+                [global]
+                const_g = 2.0
+                [camera]
+                position = [4, 5]
+                "#,
+            ),
+        );
+
+        // tables merged key by key, plain arrays replaced whole
+        assert_eq!(base["global"]["window_name"].as_str(), Some("base"));
+        assert_eq!(base["global"]["const_g"].as_float(), Some(2.0));
+        assert_eq!(base["camera"]["position"], table("p = [4, 5]")["p"]);
+        assert!(offsets.is_empty());
+    }
+}
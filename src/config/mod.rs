@@ -1,26 +1,47 @@
 mod ext;
+mod horizons;
+mod generators;
+mod include;
+mod merge;
+mod presets;
+mod random;
+mod rng;
 mod serializers;
+mod units;
+mod validate;
 
 use serializers as ser;
 
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 use three_d::{degrees, vec3, Degrees, Srgba, Vector3, Zero, Attenuation};
 
-#[derive(Serialize, Deserialize, Debug)]
+pub use crate::physics::collision::CollisionMode;
+pub use crate::recorder::OutputFormat;
+pub use horizons::HorizonsVectors;
+pub use presets::Preset;
+pub use units::{Dimension, Quantity, UnitSystem};
+pub use validate::{ConfigErrors, KeyPath, LoadOptions, Problem};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct ConfigGlobal {
     pub window_name: String,
     pub window_size: Option<(u32, u32)>,
-    pub const_g: f32,    #[serde(
+    pub const_g: f64,    #[serde(
         serialize_with = "ser::serialize_srgba",
         deserialize_with = "ser::deserialize_srgba"
     )]
     pub background_color: Srgba,
-    /// Max ms time between frames to ignore the frame
-    /// calculation, in changes of workspace, the window
-    /// will stop rendering and dt will be huge on return,
-    /// usually sending everything flying
+    /// Max ms time a frame is considered to take, in
+    /// changes of workspace, the window will stop rendering
+    /// and dt will be huge on return, this avoids the
+    /// physics trying to catch up with all of it
     pub max_frame_dt: f64,
+    /// Where everything random in the config comes from, same seed and
+    /// same config, same bodies
+    pub seed: u64,
 }
 
 impl Default for ConfigGlobal {
@@ -32,13 +53,104 @@ impl Default for ConfigGlobal {
             background_color: Srgba::new(42, 42, 42, 255),
             // Equivalent to 15Hz btw
             max_frame_dt: 66.666,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IntegratorKind {
+    /// Semi-implicit euler, what the simulation used to do
+    Euler,
+    #[serde(alias = "verlet")]
+    VelocityVerlet,
+    #[serde(alias = "leapfrog-kdk")]
+    Leapfrog,
+    #[serde(alias = "yoshida")]
+    Yoshida4,
+    /// Adaptive RK45, see `adaptive_tolerance` and `adaptive_max_substeps`
+    #[serde(alias = "rk45")]
+    DormandPrince,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SolverKind {
+    Direct,
+    /// Octree approximation, see `theta`
+    BarnesHut,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConfigPhysics {
+    /// Shift all bodies on load so the barycenter is at the
+    /// origin and at rest, no more system slowly flying away
+    pub barycentric_frame: bool,
+    /// Fixed physics tick in the simulation's time unit, `0` to step
+    /// once per frame with whatever time it took (framerate dependent)
+    pub dt: f64,
+    /// Max physics ticks per rendered frame, if the simulation
+    /// can't keep up it'll slow down instead of freezing
+    pub max_steps_per_frame: u32,
+    pub integrator: IntegratorKind,
+    /// Max local error per substep allowed to the adaptive
+    /// integrator, relative to the magnitude of the values
+    pub adaptive_tolerance: f64,
+    /// How many pieces can the adaptive integrator split a
+    /// frame into before giving up on the tolerance
+    pub adaptive_max_substeps: u32,
+    pub solver: SolverKind,
+    /// Barnes-Hut opening angle, bigger is faster and less
+    /// accurate, `0` is as exact (and slower) than direct sum
+    pub theta: f64,
+    /// Threads to compute forces with, `0` for one per core,
+    /// only makes a difference with a few hundred bodies or more
+    pub threads: usize,
+    /// Plummer softening length, gravity between bodies closer
+    /// than this stops growing, avoiding close passes flinging
+    /// them away. Bodies can override it.
+    pub softening: f64,
+    /// What bodies do when they touch, bodies can override it
+    pub collisions: CollisionMode,
+    /// Fraction of the approaching speed kept after a bounce
+    pub restitution: f64,
+    /// Simulated time between printing energy, momentum and
+    /// their drift since the start, `0` to never print them
+    pub diagnostics_interval: f64,
+    /// Simulated time the clock starts at, snapshots set it to
+    /// when they were taken
+    pub start_time: f64,
+}
+
+impl Default for ConfigPhysics {
+    fn default() -> Self {
+        Self {
+            barycentric_frame: false,
+            dt: 1.0,
+            max_steps_per_frame: 100,
+            integrator: IntegratorKind::VelocityVerlet,
+            adaptive_tolerance: 1e-6,
+            adaptive_max_substeps: 1000,
+            solver: SolverKind::Direct,
+            theta: 0.5,
+            threads: 0,
+            softening: 0.0,
+            collisions: CollisionMode::Ignore,
+            restitution: 1.0,
+            diagnostics_interval: 0.0,
+            start_time: 0.0,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct ConfigCheats {
+    /// Simulated time passing every real ms, in the simulation's time
+    /// unit so `1` is real time with the default ms. Written with units
+    /// (`"0.002 day"`) it's the same whatever `[units]` says.
     pub time_mult: f64,
 }
 
@@ -50,7 +162,7 @@ impl Default for ConfigCheats {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct ConfigCamera {
     #[serde(
@@ -74,6 +186,9 @@ pub struct ConfigCamera {
     )]
     pub fov: Degrees,
     pub render_distance: (f32, f32),
+    /// Keep the camera looking at the system's barycenter as it
+    /// moves, dragging the camera along
+    pub lock_to_barycenter: bool,
 }
 
 impl Default for ConfigCamera {
@@ -84,33 +199,223 @@ impl Default for ConfigCamera {
             up: Vector3::unit_z(),
             fov: degrees(45.0),
             render_distance: (0.01, f32::MAX / 2.0),
+            lock_to_barycenter: false,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConfigBody {
     pub name: String,
+    /// Relative to the parent if there's an `orbit`
     #[serde(
+        default = "Vector3::zero",
         serialize_with = "ser::serialize_vector3",
         deserialize_with = "ser::deserialize_vector3"
     )]
-    pub position: Vector3<f32>,
+    pub position: Vector3<f64>,
+    /// Relative to the parent if there's an `orbit`
     #[serde(
+        default = "Vector3::zero",
         serialize_with = "ser::serialize_vector3",
         deserialize_with = "ser::deserialize_vector3"
     )]
-    pub velocity: Vector3<f32>,
+    pub velocity: Vector3<f64>,
     #[serde(
         serialize_with = "ser::serialize_srgba",
         deserialize_with = "ser::deserialize_srgba"
     )]
     pub color: Srgba,
     pub radius: f32,
-    pub mass: f32,
+    pub mass: f64,
+    /// Plummer softening length, overrides `physics.softening`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub softening: Option<f64>,
+    /// Overrides `physics.collisions`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collision: Option<CollisionMode>,
+    /// Start on a keplerian orbit around another body instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orbit: Option<ConfigOrbit>,
+}
+
+/// Keplerian orbital elements around `parent`, angles in degrees over the
+/// xy plane. Position along the orbit is given by either anomaly,
+/// defaulting to periapsis.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigOrbit {
+    pub parent: String,
+    /// Its absolute value for hyperbolic orbits (`eccentricity > 1`)
+    pub semi_major_axis: f64,
+    #[serde(default)]
+    pub eccentricity: f64,
+    #[serde(default)]
+    pub inclination: f64,
+    #[serde(default)]
+    pub longitude_of_ascending_node: f64,
+    #[serde(default)]
+    pub argument_of_periapsis: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub true_anomaly: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_anomaly: Option<f64>,
+}
+
+/// A body from a JPL Horizons vector table export, at its first state
+/// vectors. Everything set here wins over what the file says.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigImport {
+    /// Relative to the file it's written in
+    pub path: PathBuf,
+    /// The file's target body name if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// From the GM in the file's header if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass: Option<f64>,
+    /// From the mean radius in the file's header if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f32>,
+    #[serde(
+        default = "default_body_color",
+        serialize_with = "ser::serialize_srgba",
+        deserialize_with = "ser::deserialize_srgba"
+    )]
+    pub color: Srgba,
+}
+
+impl ConfigImport {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            name: None,
+            mass: None,
+            radius: None,
+            color: default_body_color(),
+        }
+    }
+}
+
+/// For bodies nobody picked a color for
+fn default_body_color() -> Srgba {
+    Srgba::new(200, 200, 200, 255)
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// Lots of bodies placed at random, see `GeneratorKind`. They're kept
+/// from overlapping each other, not the bodies already there.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigGenerator {
+    #[serde(flatten)]
+    pub kind: GeneratorKind,
+    /// Bodies to make, pairs of them for binaries
+    pub count: usize,
+    /// Same seed, same bodies. Comes from `global.seed` if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Bodies are called `{name}-{i}`, after the kind if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// `[min, max]` each body's mass is picked from
+    pub mass: (f64, f64),
+    #[serde(default)]
+    pub mass_distribution: MassDistribution,
+    /// `[min, max]` each body's radius is picked from, evenly
+    pub body_radius: (f64, f64),
+    #[serde(
+        default = "default_body_color",
+        serialize_with = "ser::serialize_srgba",
+        deserialize_with = "ser::deserialize_srgba"
+    )]
+    pub color: Srgba,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum GeneratorKind {
+    /// Circular orbits around `center`, as many bodies at every distance
+    /// (so thinning out further away), like a protoplanetary disk
+    Disk {
+        center: String,
+        inner_radius: f64,
+        outer_radius: f64,
+        /// Spread of the heights over the disk's plane
+        #[serde(default)]
+        thickness: f64,
+        /// Perpendicular to the disk's plane, which way is up for the
+        /// orbits
+        #[serde(
+            default = "Vector3::unit_z",
+            serialize_with = "ser::serialize_vector3",
+            deserialize_with = "ser::deserialize_vector3"
+        )]
+        normal: Vector3<f64>,
+    },
+    /// Circular orbits around `center` spread evenly over a flat ring,
+    /// like a planet's
+    Ring {
+        center: String,
+        inner_radius: f64,
+        outer_radius: f64,
+        #[serde(
+            default = "Vector3::unit_z",
+            serialize_with = "ser::serialize_vector3",
+            deserialize_with = "ser::deserialize_vector3"
+        )]
+        normal: Vector3<f64>,
+    },
+    /// Star cluster in equilibrium, half its mass within about 1.3
+    /// `scale_radius` of `position`
+    Plummer {
+        scale_radius: f64,
+        #[serde(
+            default = "Vector3::zero",
+            serialize_with = "ser::serialize_vector3",
+            deserialize_with = "ser::deserialize_vector3"
+        )]
+        position: Vector3<f64>,
+        #[serde(
+            default = "Vector3::zero",
+            serialize_with = "ser::serialize_vector3",
+            deserialize_with = "ser::deserialize_vector3"
+        )]
+        velocity: Vector3<f64>,
+    },
+    /// Pairs orbiting each other in random planes, their barycenters
+    /// evenly spread within `region_radius` of `position`
+    Binaries {
+        region_radius: f64,
+        /// `[min, max]` semi major axis, picked evenly in log scale
+        separation: (f64, f64),
+        /// `[min, max]`, circular orbits if not set
+        #[serde(default)]
+        eccentricity: (f64, f64),
+        #[serde(
+            default = "Vector3::zero",
+            serialize_with = "ser::serialize_vector3",
+            deserialize_with = "ser::deserialize_vector3"
+        )]
+        position: Vector3<f64>,
+        #[serde(
+            default = "Vector3::zero",
+            serialize_with = "ser::serialize_vector3",
+            deserialize_with = "ser::deserialize_vector3"
+        )]
+        velocity: Vector3<f64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MassDistribution {
+    #[default]
+    Uniform,
+    /// As many bodies of every order of magnitude
+    LogUniform,
+    /// Mostly light ones, `dN/dm ∝ m^-2.35` like newly formed stars
+    Salpeter,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(default)]
 pub struct ConfigLights {
     pub directional: Vec<ConfigDirectionalLight>,
@@ -119,7 +424,7 @@ pub struct ConfigLights {
     pub spotlight: Vec<ConfigSpotLight>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConfigDirectionalLight {
     pub intensity: f32,
     #[serde(
@@ -134,7 +439,7 @@ pub struct ConfigDirectionalLight {
     pub direction: Vector3<f32>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConfigAmbientLight {
     pub intensity: f32,
     #[serde(
@@ -144,7 +449,7 @@ pub struct ConfigAmbientLight {
     pub color: Srgba,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConfigPointLight {
     pub intensity: f32,
     #[serde(
@@ -164,7 +469,7 @@ pub struct ConfigPointLight {
     pub attenuation: Attenuation,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConfigSpotLight {
     pub intensity: f32,
     #[serde(
@@ -196,58 +501,78 @@ pub struct ConfigSpotLight {
 }
 
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ConfigDebug {
+    /// Show the orbits overlay from the start, F3 toggles it
+    pub overlay: bool,
+    /// Body to measure every orbit around, each body's
+    /// dominant attractor if not set
+    pub orbit_reference: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ConfigOutput {
+    /// File to record trajectories to, nothing's recorded if not set
+    pub path: Option<PathBuf>,
+    /// Guessed from `path`'s extension if not set, csv if that fails too
+    pub format: Option<OutputFormat>,
+    /// Simulated time between samples, `0` samples every tick
+    pub interval: f64,
+    /// Also record the total energy with every sample
+    pub energy: bool,
+    /// File to record a replay of the run to, everything needed
+    /// to play it back exactly as it happened
+    pub replay: Option<PathBuf>,
+}
+
+/// What the plain numbers in the config and the simulation mean, each
+/// a unit (`"AU"`) or an amount of one (`"1e9 m"`). Having this derives
+/// `global.const_g` from them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConfigUnits {
+    pub length: String,
+    pub mass: String,
+    pub time: String,
+}
+
+impl Default for ConfigUnits {
+    fn default() -> Self {
+        Self {
+            length: String::from("mm"),
+            mass: String::from("kg"),
+            time: String::from("ms"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(default)]
 pub struct Config {
+    /// Starts from this one, the rest of the config goes on top with
+    /// its bodies and lights added to the preset's
+    pub preset: Option<Preset>,
+    /// Files this one goes on top of, after the preset, relative to it.
+    /// Their bodies and lights are added, the rest this one overrides.
+    /// `units`, `preset` and `global.seed` can only be in this one.
+    pub include: Vec<PathBuf>,
     pub global: ConfigGlobal,
+    pub units: Option<ConfigUnits>,
     pub camera: ConfigCamera,
     pub bodies: Vec<ConfigBody>,
+    /// Added to `bodies` as the config is loaded
+    pub import: Vec<ConfigImport>,
+    /// Expanded into `bodies` as the config is loaded, after `import`
+    pub generators: Vec<ConfigGenerator>,
     pub lights: ConfigLights,
+    pub physics: ConfigPhysics,
+    pub debug: ConfigDebug,
+    pub output: ConfigOutput,
     pub cheats: ConfigCheats,
 }
 
 pub fn example_config() -> Config {
-    let mut config = Config::default();
-
-    config.bodies.push(ConfigBody {
-        name: String::from("sun"),
-        position: vec3(0.0, 0.0, 0.0),
-        velocity: vec3(0.0, 0.0, 0.0),
-        color: Srgba {r: 255, g: 255, b: 0, a: 255},
-        radius: 1.3,
-        mass: 2.6e6,
-    });
-
-    config.bodies.push(ConfigBody {
-        name: String::from("earth"),
-        position: vec3(7.0, 0.0, 0.0),
-        velocity: vec3(0.0, 0.004, 0.0),
-        color: Srgba {r: 0, g: 100, b: 200, a: 255},
-        radius: 0.8,
-        mass: 4.5e5,
-    });
-
-    // as I said, "moon"
-    config.bodies.push(ConfigBody {
-        name: String::from("moon"),
-        position: vec3(8.5, 0.0, 0.0),
-        velocity: vec3(0.0, -0.0007, 0.0),
-        color: Srgba {r: 150, g: 200, b: 200, a: 255},
-        radius: 0.2,
-        mass: 3e2,
-    });
-
-
-    config.lights.directional.push(ConfigDirectionalLight {
-        intensity: 1.0,
-        color: Srgba::WHITE,
-        direction: vec3(0.0, -0.5, -0.5),
-    });
-
-    config.lights.ambient.push(ConfigAmbientLight {
-        intensity: 0.1,
-        color: Srgba::WHITE,
-    });
-
-    config
+    Preset::Example.config()
 }
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Ready made scenarios, `preset = "..."` starts a config from one of
+/// them. Every quantity in them has units, so they mean the same thing
+/// whatever `[units]` the config picks.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// The toy sun, earth and moon
+    Example,
+    /// The Sun, planets and major moons at J2000
+    SolarSystem,
+}
+
+impl Preset {
+    pub fn source(self) -> &'static str {
+        match self {
+            Self::Example => include_str!("presets/example.toml"),
+            Self::SolarSystem => include_str!("presets/solar_system.toml"),
+        }
+    }
+
+    /// The preset on its own, nothing changed
+    pub fn config(self) -> super::Config {
+        super::Config::from_toml(self.source(), &self.to_string())
+            .unwrap_or_else(|err| panic!("bundled preset doesn't load: {err}"))
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Example => "example",
+            Self::SolarSystem => "solar-system",
+        };
+        write!(f, "{name} preset")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn bundled_presets_load() {
+        for preset in [Preset::Example, Preset::SolarSystem] {
+            assert!(!preset.config().bodies.is_empty(), "{preset}");
+        }
+    }
+
+    #[test]
+    fn presets_follow_the_units_put_over_them() {
+        let in_au = Preset::SolarSystem.config();
+        let in_km = Config::from_toml(
+            r#"
+            preset = "solar-system"
+
+            [units]
+            length = "km"
+            "#,
+            "test",
+        )
+        .unwrap_or_else(|err| panic!("{err}"));
+
+        assert_eq!(in_km.bodies.len(), in_au.bodies.len());
+        let earth = |config: &Config| {
+            let earth = config.bodies.iter().find(|body| body.name == "earth");
+            earth.unwrap().position.x
+        };
+        let (au, km) = (earth(&in_au), earth(&in_km));
+        assert!((km / au - 1.495_978_707e8).abs() < 1e-6 * 1.495_978_707e8);
+    }
+}
@@ -0,0 +1,124 @@
+use std::ops::Range;
+
+use toml_edit::{Item, Value};
+
+use super::{
+    rng::Rng,
+    units::{self, Dimension, UnitSystem},
+    validate::{KeyPath, Problem},
+};
+
+/// Every `{ uniform = [min, max] }` or `{ normal = [mean, std_dev] }`
+/// written in place of a number in `bodies`, and the number picked for
+/// it. Each one is picked from its own path and `seed`, so adding or
+/// removing one doesn't change the others.
+pub(super) fn resolve(
+    doc: &toml_edit::ImDocument<&str>,
+    units: &UnitSystem,
+    seed: u64,
+    problems: &mut Vec<Problem>,
+) -> Vec<(Range<usize>, String)> {
+    let mut picked = vec![];
+    let mut found = |value: &Value, path: KeyPath| match pick(value, &path, units, seed) {
+        Some(Ok((span, number))) => picked.push((span, format!("{number:?}"))),
+        Some(Err(message)) => problems.push(Problem {
+            span: value.span(),
+            path,
+            message,
+        }),
+        None => {}
+    };
+    if let Some(bodies) = doc.get("bodies") {
+        walk(bodies, KeyPath::default().key("bodies"), &mut found);
+    }
+    picked
+}
+
+/// Calls `found` with every value under `item`, tables and arrays
+/// included
+fn walk(item: &Item, path: KeyPath, found: &mut impl FnMut(&Value, KeyPath)) {
+    match item {
+        Item::Value(value) => walk_value(value, path, found),
+        Item::Table(table) => {
+            for (key, item) in table.iter() {
+                walk(item, path.key(key), found);
+            }
+        }
+        Item::ArrayOfTables(array) => {
+            for (i, table) in array.iter().enumerate() {
+                for (key, item) in table.iter() {
+                    walk(item, path.index(i).key(key), found);
+                }
+            }
+        }
+        Item::None => {}
+    }
+}
+
+fn walk_value(value: &Value, path: KeyPath, found: &mut impl FnMut(&Value, KeyPath)) {
+    found(value, path.clone());
+    match value {
+        Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                walk_value(value, path.index(i), found);
+            }
+        }
+        Value::InlineTable(table) => {
+            for (key, value) in table.iter() {
+                walk_value(value, path.key(key), found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The number for `value` if it's a random one
+fn pick(
+    value: &Value,
+    path: &KeyPath,
+    units: &UnitSystem,
+    seed: u64,
+) -> Option<Result<(Range<usize>, f64), String>> {
+    let span = value.span()?;
+    let table = value.as_inline_table()?;
+    let (kind, args) = table.iter().next().filter(|_| table.len() == 1)?;
+    if kind != "uniform" && kind != "normal" {
+        return None;
+    }
+
+    let dimension = units::dimension_at(path);
+    let number = |value: &Value| match value {
+        Value::Float(float) => Ok(*float.value()),
+        Value::Integer(integer) => Ok(*integer.value() as f64),
+        Value::String(string) => match dimension {
+            Some(dimension) => units::to_number(string.value(), dimension, units),
+            None => units::to_number(string.value(), Dimension::NONE, units)
+                .map_err(|_| format!("{:?} doesn't take units here", string.value())),
+        },
+        _ => Err(format!("{kind} takes numbers, got {value}")),
+    };
+    let args = match args.as_array().map(|args| args.iter().collect::<Vec<_>>()) {
+        Some(args) if args.len() == 2 => args,
+        _ => {
+            let expected = if kind == "uniform" {
+                "[min, max]"
+            } else {
+                "[mean, std_dev]"
+            };
+            return Some(Err(format!("{kind} takes {expected}")));
+        }
+    };
+    let (a, b) = match (number(args[0]), number(args[1])) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(err), _) | (_, Err(err)) => return Some(Err(err)),
+    };
+
+    let mut rng = Rng::derived(seed, &path.to_string());
+    let number = match kind {
+        "uniform" if a <= b => rng.uniform(a, b),
+        "uniform" => return Some(Err(format!("min ({a}) is over max ({b})"))),
+        _ if b >= 0.0 => rng.normal(a, b),
+        _ => return Some(Err(format!("std_dev can't be negative, got {b}"))),
+    };
+    Some(Ok((span, number)))
+}
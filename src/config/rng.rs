@@ -0,0 +1,79 @@
+use std::f64::consts::TAU;
+
+use three_d::{vec3, Vector3};
+
+/// xoshiro256**, small and plenty for placing bodies. Same seed, same
+/// numbers, on every platform.
+#[derive(Debug, Clone)]
+pub(super) struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 to spread the seed over the whole state, so nearby
+        // seeds don't start out alike
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        Self {
+            state: [next(), next(), next(), next()],
+        }
+    }
+
+    /// Its own stream for `key` out of `seed`, so what one thing picks
+    /// doesn't depend on how many numbers others picked before it
+    pub fn derived(seed: u64, key: &str) -> Self {
+        // FNV-1a
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        Self::new(seed ^ hash)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.state;
+        let result = s1.wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = *s1 << 17;
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+        result
+    }
+
+    /// In `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// In `(0, 1]`, for taking logs of
+    fn next_f64_nonzero(&mut self) -> f64 {
+        1.0 - self.next_f64()
+    }
+
+    pub fn uniform(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+
+    pub fn normal(&mut self, mean: f64, std_dev: f64) -> f64 {
+        // Box-Muller, throwing the second one away keeps it stateless
+        let radius = (-2.0 * self.next_f64_nonzero().ln()).sqrt();
+        mean + std_dev * radius * (TAU * self.next_f64()).cos()
+    }
+
+    /// Evenly spread over the unit sphere
+    pub fn direction(&mut self) -> Vector3<f64> {
+        let z = self.uniform(-1.0, 1.0);
+        let angle = TAU * self.next_f64();
+        let r = (1.0 - z * z).sqrt();
+        vec3(r * angle.cos(), r * angle.sin(), z)
+    }
+}
@@ -0,0 +1,384 @@
+use std::{fmt, ops::Range, str::FromStr};
+
+use super::validate::{KeyPath, Problem};
+
+/// In m³/(kg·s²)
+pub(super) const GRAVITATIONAL_CONSTANT_SI: f64 = 6.6743e-11;
+
+/// Powers of length, mass and time a quantity is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimension {
+    pub length: i8,
+    pub mass: i8,
+    pub time: i8,
+}
+
+impl Dimension {
+    pub const NONE: Self = Self::new(0, 0, 0);
+    pub const LENGTH: Self = Self::new(1, 0, 0);
+    pub const MASS: Self = Self::new(0, 1, 0);
+    pub const TIME: Self = Self::new(0, 0, 1);
+    pub const VELOCITY: Self = Self::new(1, 0, -1);
+    /// GM, what orbits actually depend on
+    pub const GRAVITATIONAL_PARAMETER: Self = Self::new(3, 0, -2);
+
+    const fn new(length: i8, mass: i8, time: i8) -> Self {
+        Self { length, mass, time }
+    }
+
+    fn pow(self, n: i8) -> Self {
+        Self::new(self.length * n, self.mass * n, self.time * n)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.length + other.length,
+            self.mass + other.mass,
+            self.time + other.time,
+        )
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            Self::NONE => "a plain number",
+            Self::LENGTH => "a length",
+            Self::MASS => "a mass",
+            Self::TIME => "a time",
+            Self::VELOCITY => "a velocity",
+            _ => return write!(f, "m^{} kg^{} s^{}", self.length, self.mass, self.time),
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Units that can be written, with what they are in SI
+const UNITS: &[(&str, f64, Dimension)] = &[
+    ("m", 1.0, Dimension::LENGTH),
+    ("mm", 1e-3, Dimension::LENGTH),
+    ("cm", 1e-2, Dimension::LENGTH),
+    ("km", 1e3, Dimension::LENGTH),
+    ("AU", 1.495_978_707e11, Dimension::LENGTH),
+    ("au", 1.495_978_707e11, Dimension::LENGTH),
+    ("ly", 9.460_730_472_580_8e15, Dimension::LENGTH),
+    ("pc", 3.085_677_581_491_367e16, Dimension::LENGTH),
+    ("R_sun", 6.957e8, Dimension::LENGTH),
+    ("R_earth", 6.371e6, Dimension::LENGTH),
+    ("R_jupiter", 7.1492e7, Dimension::LENGTH),
+    ("kg", 1.0, Dimension::MASS),
+    ("g", 1e-3, Dimension::MASS),
+    ("t", 1e3, Dimension::MASS),
+    ("M_sun", 1.988_47e30, Dimension::MASS),
+    ("M_earth", 5.9722e24, Dimension::MASS),
+    ("M_jupiter", 1.898_13e27, Dimension::MASS),
+    ("M_moon", 7.342e22, Dimension::MASS),
+    ("s", 1.0, Dimension::TIME),
+    ("ms", 1e-3, Dimension::TIME),
+    ("min", 60.0, Dimension::TIME),
+    ("h", 3600.0, Dimension::TIME),
+    ("d", 86400.0, Dimension::TIME),
+    ("day", 86400.0, Dimension::TIME),
+    ("yr", 3.155_76e7, Dimension::TIME),
+    ("year", 3.155_76e7, Dimension::TIME),
+];
+
+/// A number with units, like `"29.78 km/s"` or `"1 M_sun"`, kept in SI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub dimension: Dimension,
+}
+
+impl FromStr for Quantity {
+    type Err = String;
+
+    /// Units are multiplied with `*` and divided with `/`, each one
+    /// raised to an optional `^n`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (number, units) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let mut quantity = Quantity {
+            value: number
+                .parse()
+                .map_err(|_| format!("{number:?} isn't a number"))?,
+            dimension: Dimension::NONE,
+        };
+
+        let units: String = units.chars().filter(|c| !c.is_whitespace()).collect();
+        if units.is_empty() {
+            return Ok(quantity);
+        }
+        let mut divide = false;
+        for term in units.split_inclusive(['*', '/']) {
+            let next_divides = term.ends_with('/');
+            let term = term.trim_end_matches(['*', '/']);
+            let (name, power) = match term.split_once('^') {
+                Some((name, power)) => (
+                    name,
+                    power
+                        .parse::<i8>()
+                        .map_err(|_| format!("bad power {power:?} in {units:?}"))?,
+                ),
+                None => (term, 1),
+            };
+            let &(_, factor, dimension) = UNITS
+                .iter()
+                .find(|(unit, ..)| *unit == name)
+                .ok_or_else(|| {
+                    let known: Vec<_> = UNITS.iter().map(|(unit, ..)| *unit).collect();
+                    format!("unknown unit {name:?}, known ones are {}", known.join(", "))
+                })?;
+
+            let power = if divide { -power } else { power };
+            quantity.value *= factor.powi(power as i32);
+            quantity.dimension = quantity.dimension.mul(dimension.pow(power));
+            divide = next_divides;
+        }
+        Ok(quantity)
+    }
+}
+
+/// What a unit of length, mass and time in the simulation is, in SI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnitSystem {
+    pub length: f64,
+    pub mass: f64,
+    pub time: f64,
+}
+
+impl Default for UnitSystem {
+    /// mm, kg and ms, what the numbers always meant
+    fn default() -> Self {
+        Self {
+            length: 1e-3,
+            mass: 1.0,
+            time: 1e-3,
+        }
+    }
+}
+
+impl UnitSystem {
+    /// `quantity` as a number in this system's units
+    pub fn to_simulation(&self, quantity: Quantity) -> f64 {
+        let Dimension { length, mass, time } = quantity.dimension;
+        quantity.value
+            / (self.length.powi(length as i32)
+                * self.mass.powi(mass as i32)
+                * self.time.powi(time as i32))
+    }
+
+    pub fn gravitational_constant(&self) -> f64 {
+        GRAVITATIONAL_CONSTANT_SI * self.mass * self.time.powi(2) / self.length.powi(3)
+    }
+}
+
+/// Values that can be written with units, `[]` standing for every
+/// element of an array. Vectors take units on each component.
+const QUANTITIES: &[(&str, Dimension)] = &[
+    ("camera.position[]", Dimension::LENGTH),
+    ("camera.target[]", Dimension::LENGTH),
+    ("camera.render_distance[]", Dimension::LENGTH),
+    ("physics.dt", Dimension::TIME),
+    ("physics.softening", Dimension::LENGTH),
+    ("physics.diagnostics_interval", Dimension::TIME),
+    ("physics.start_time", Dimension::TIME),
+    ("output.interval", Dimension::TIME),
+    ("cheats.time_mult", Dimension::TIME),
+    ("bodies[].position[]", Dimension::LENGTH),
+    ("bodies[].velocity[]", Dimension::VELOCITY),
+    ("bodies[].radius", Dimension::LENGTH),
+    ("bodies[].mass", Dimension::MASS),
+    ("bodies[].softening", Dimension::LENGTH),
+    ("bodies[].orbit.semi_major_axis", Dimension::LENGTH),
+    ("import[].mass", Dimension::MASS),
+    ("import[].radius", Dimension::LENGTH),
+    ("generators[].mass[]", Dimension::MASS),
+    ("generators[].body_radius[]", Dimension::LENGTH),
+    ("generators[].inner_radius", Dimension::LENGTH),
+    ("generators[].outer_radius", Dimension::LENGTH),
+    ("generators[].thickness", Dimension::LENGTH),
+    ("generators[].scale_radius", Dimension::LENGTH),
+    ("generators[].region_radius", Dimension::LENGTH),
+    ("generators[].separation[]", Dimension::LENGTH),
+    ("generators[].position[]", Dimension::LENGTH),
+    ("generators[].velocity[]", Dimension::VELOCITY),
+];
+
+/// `value` as a plain number in `units`, if it's `dimension` or has no
+/// units at all
+pub(super) fn to_number(
+    value: &str,
+    dimension: Dimension,
+    units: &UnitSystem,
+) -> Result<f64, String> {
+    let quantity = value.parse::<Quantity>()?;
+    if quantity.dimension == Dimension::NONE {
+        Ok(quantity.value)
+    } else if quantity.dimension == dimension {
+        Ok(units.to_simulation(quantity))
+    } else {
+        Err(format!("should be {dimension}, got {}", quantity.dimension))
+    }
+}
+
+/// What the value at `path` is measured in, if it takes units at all
+pub(super) fn dimension_at(path: &KeyPath) -> Option<Dimension> {
+    // `bodies[2].position[0]` to `bodies[].position[]`
+    let mut pattern = String::new();
+    let mut in_index = false;
+    for c in path.to_string().chars() {
+        match c {
+            '[' => {
+                pattern.push_str("[]");
+                in_index = true;
+            }
+            ']' => in_index = false,
+            c if !in_index => pattern.push(c),
+            _ => {}
+        }
+    }
+    QUANTITIES
+        .iter()
+        .find(|(quantity, _)| *quantity == pattern)
+        .map(|&(_, dimension)| dimension)
+}
+
+/// Every quantity with units in `doc` and the plain number in `units` to
+/// put in its place
+pub(super) fn convert(
+    doc: &toml_edit::ImDocument<&str>,
+    units: &UnitSystem,
+    problems: &mut Vec<Problem>,
+) -> Vec<(Range<usize>, String)> {
+    let (mut replacements, mut found_problems) = (vec![], vec![]);
+    for &(pattern, dimension) in QUANTITIES {
+        let mut segments = vec![];
+        for key in pattern.split('.') {
+            match key.strip_suffix("[]") {
+                Some(key) => segments.extend([Some(key), None]),
+                None => segments.push(Some(key)),
+            }
+        }
+        find(
+            doc.as_item(),
+            &segments,
+            KeyPath::default(),
+            &mut |item, path| {
+                let Some(value) = item.as_str() else { return };
+                let Some(span) = item.span() else { return };
+                match to_number(value, dimension, units) {
+                    Ok(value) => replacements.push((span, format!("{value:?}"))),
+                    Err(message) => found_problems.push(Problem {
+                        path,
+                        message,
+                        span: Some(span),
+                    }),
+                }
+            },
+        );
+    }
+
+    // in the order they're written rather than by pattern
+    found_problems.sort_by_key(|problem: &Problem| problem.span.as_ref().map(|span| span.start));
+    problems.extend(found_problems);
+    replacements
+}
+
+/// `source` with each span in `replacements` replaced, they can't overlap
+pub(super) fn splice(source: &str, mut replacements: Vec<(Range<usize>, String)>) -> String {
+    replacements.sort_by_key(|(span, _)| span.start);
+    let mut spliced = String::with_capacity(source.len());
+    let mut at = 0;
+    for (span, value) in replacements {
+        spliced.push_str(&source[at..span.start]);
+        spliced.push_str(&value);
+        at = span.end;
+    }
+    spliced.push_str(&source[at..]);
+    spliced
+}
+
+/// Calls `found` with every item `segments` leads to, `None` going
+/// through all elements of an array
+fn find(
+    item: &toml_edit::Item,
+    segments: &[Option<&str>],
+    path: KeyPath,
+    found: &mut impl FnMut(&toml_edit::Item, KeyPath),
+) {
+    let Some((segment, rest)) = segments.split_first() else {
+        return found(item, path);
+    };
+    match segment {
+        Some(key) => {
+            if let Some(item) = item.get(*key) {
+                find(item, rest, path.key(key), found);
+            }
+        }
+        None => {
+            let mut i = 0;
+            while let Some(item) = item.get(i) {
+                find(item, rest, path.index(i), found);
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_compound_units() {
+        let speed: Quantity = "29.78 km/s".parse().unwrap();
+        assert_eq!(speed.dimension, Dimension::VELOCITY);
+        assert!((speed.value - 29_780.0).abs() < 1e-9);
+
+        let mass: Quantity = "1 M_sun".parse().unwrap();
+        assert_eq!(mass.dimension, Dimension::MASS);
+        assert_eq!(mass.value, 1.988_47e30);
+
+        let gm: Quantity = "1 km^3 / s^2".parse().unwrap();
+        assert_eq!(gm.dimension, Dimension::GRAVITATIONAL_PARAMETER);
+        assert_eq!(gm.value, 1e9);
+    }
+
+    #[test]
+    fn plain_numbers_have_no_dimension() {
+        let quantity: Quantity = "1e-10".parse().unwrap();
+        assert_eq!(quantity.dimension, Dimension::NONE);
+        assert_eq!(quantity.value, 1e-10);
+    }
+
+    #[test]
+    fn rejects_bad_quantities() {
+        let err = "1 m^x".parse::<Quantity>().unwrap_err();
+        assert!(err.contains("bad power"), "{err}");
+        let err = "1 parsec".parse::<Quantity>().unwrap_err();
+        assert!(err.contains("unknown unit"), "{err}");
+        let err = "one km".parse::<Quantity>().unwrap_err();
+        assert!(err.contains("isn't a number"), "{err}");
+    }
+
+    #[test]
+    fn converts_to_simulation_units() {
+        let units = UnitSystem {
+            length: 1.495_978_707e11,
+            mass: 1.988_47e30,
+            time: 86400.0,
+        };
+        assert_eq!(to_number("1 AU", Dimension::LENGTH, &units), Ok(1.0));
+        assert_eq!(to_number("2", Dimension::LENGTH, &units), Ok(2.0));
+        let velocity = to_number("1 AU/day", Dimension::VELOCITY, &units).unwrap();
+        assert!((velocity - 1.0).abs() < 1e-12);
+        assert!((units.gravitational_constant() - 2.959_1e-4).abs() < 1e-7);
+    }
+
+    #[test]
+    fn rejects_the_wrong_dimension() {
+        let err = to_number("1 s", Dimension::LENGTH, &UnitSystem::default()).unwrap_err();
+        assert_eq!(err, "should be a length, got a time");
+    }
+}
@@ -0,0 +1,692 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use three_d::MetricSpace;
+
+use serde::Deserialize;
+
+use super::{
+    include,
+    merge::{merge, Offsets},
+    random,
+    rng::Rng,
+    units, ConfigImport, ConfigUnits, Preset, UnitSystem,
+};
+use crate::physics::collision::{touching_pairs, CollisionMode};
+
+/// Where in the config something is, like `bodies[2].mass`
+#[derive(Debug, Clone, Default)]
+pub struct KeyPath(Vec<Segment>);
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl KeyPath {
+    pub fn key(&self, key: &str) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Key(key.to_owned()));
+        path
+    }
+
+    pub fn index(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(Segment::Index(index));
+        path
+    }
+
+    /// Span of the deepest part of the path that's written in `doc`
+    pub(super) fn span_in(&self, doc: &toml_edit::ImDocument<&str>) -> Option<Range<usize>> {
+        let mut item = doc.as_item();
+        let mut span = None;
+        for segment in &self.0 {
+            let next = match segment {
+                Segment::Key(key) => item.get(key.as_str()),
+                Segment::Index(i) => item.get(*i),
+            };
+            let Some(next) = next else { break };
+            item = next;
+            span = item.span().or(span);
+        }
+        span
+    }
+
+    /// Same path in a file that was merged on top of others, `None` if
+    /// it's in something that came from them
+    fn in_merged(&self, offsets: &Offsets) -> Option<Self> {
+        let mut path = Self::default();
+        for segment in &self.0 {
+            let segment = match (segment, offsets.get(&path.to_string())) {
+                (Segment::Index(i), Some(offset)) => Segment::Index(i.checked_sub(*offset)?),
+                (segment, _) => segment.clone(),
+            };
+            path.0.push(segment);
+        }
+        Some(path)
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{key}")?,
+                Segment::Key(key) => write!(f, ".{key}")?,
+                Segment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub path: KeyPath,
+    pub message: String,
+    /// Bytes of the source it's about, if it came from one
+    pub span: Option<Range<usize>>,
+}
+
+/// Everything wrong with a config, all at once
+#[derive(Debug)]
+pub struct ConfigErrors {
+    /// What to call the config, usually the file it came from
+    pub origin: String,
+    source: Option<String>,
+    pub problems: Vec<Problem>,
+}
+
+impl ConfigErrors {
+    pub(super) fn new(origin: &str, source: Option<&str>, problems: Vec<Problem>) -> Self {
+        Self {
+            origin: origin.to_owned(),
+            source: source.map(str::to_owned),
+            problems,
+        }
+    }
+}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.problems.len();
+        write!(
+            f,
+            "{count} problem{} in {}",
+            if count == 1 { "" } else { "s" },
+            self.origin
+        )?;
+        for problem in &self.problems {
+            writeln!(f)?;
+            let path = if problem.path.0.is_empty() {
+                String::new()
+            } else {
+                format!("{}: ", problem.path)
+            };
+            let located = problem.span.clone().zip(self.source.as_deref());
+            let Some((span, source)) = located else {
+                write!(f, "{}: {path}{}", self.origin, problem.message)?;
+                continue;
+            };
+
+            let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[span.start..]
+                .find('\n')
+                .map_or(source.len(), |i| span.start + i);
+            let line = &source[line_start..line_end];
+            let line_number = source[..span.start].matches('\n').count() + 1;
+            let column = source[line_start..span.start].chars().count() + 1;
+            let width = source[span.start..span.end.min(line_end)]
+                .chars()
+                .count()
+                .max(1);
+
+            writeln!(
+                f,
+                "{}:{line_number}:{column}: {path}{}",
+                self.origin, problem.message
+            )?;
+            let gutter = " ".repeat(line_number.to_string().len());
+            writeln!(f, "{gutter} |")?;
+            writeln!(f, "{line_number} | {line}")?;
+            write!(
+                f,
+                "{gutter} | {}{}",
+                " ".repeat(column - 1),
+                "^".repeat(width)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// What loading a config can be told besides the file itself
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Instead of `global.seed`, up to `i64::MAX` like it
+    pub seed: Option<u64>,
+    /// File the config was read from, `include`s are relative to it.
+    /// The working directory if not set.
+    pub path: Option<PathBuf>,
+    /// Horizons exports to import after the config's `import`
+    pub imports: Vec<PathBuf>,
+    /// `(key, value)` put on top of everything else, keys are dotted
+    /// like `physics.dt` and values are TOML, a string if they aren't
+    pub overrides: Vec<(String, String)>,
+}
+
+impl LoadOptions {
+    /// `overrides` as if they were a file, but for `global.seed` which
+    /// has to be known before reading anything
+    fn overrides_source(&self) -> String {
+        let mut source = String::new();
+        for (key, value) in self
+            .overrides
+            .iter()
+            .filter(|(key, _)| key != "global.seed")
+        {
+            let value = match format!("value = {value}").parse::<toml::Table>() {
+                Ok(_) => value.clone(),
+                Err(_) => toml_edit::Value::from(value.as_str()).to_string(),
+            };
+            source += &format!("{key} = {value}\n");
+        }
+        source
+    }
+}
+
+impl super::Config {
+    /// Parses a config file's contents, `origin` is what to call it in
+    /// errors. Checks everything `validate` does, plus keys that don't
+    /// mean anything, and points at where each problem is in `source`.
+    pub fn from_toml(source: &str, origin: &str) -> Result<Self, ConfigErrors> {
+        Self::from_toml_with(source, origin, &LoadOptions::default())
+    }
+
+    /// `from_toml`, with `options` on top of what the file says
+    pub fn from_toml_with(
+        source: &str,
+        origin: &str,
+        options: &LoadOptions,
+    ) -> Result<Self, ConfigErrors> {
+        let fail = |message: String, span: Option<Range<usize>>| {
+            let problem = Problem {
+                path: KeyPath::default(),
+                message,
+                span,
+            };
+            ConfigErrors::new(origin, Some(source), vec![problem])
+        };
+        let doc = toml_edit::ImDocument::parse(source)
+            .map_err(|err| fail(err.message().to_owned(), err.span()))?;
+
+        // everything else can be written in these units and goes on top
+        // of that preset
+        let Head {
+            units,
+            preset,
+            global,
+        } = toml::from_str(source).map_err(|err| fail(err.message().to_owned(), err.span()))?;
+        let preset_units = preset.and_then(|preset| {
+            let head: Head = toml::from_str(preset.source()).expect("bundled presets parse");
+            head.units
+        });
+        let units = match preset_units {
+            Some(mut preset_units) => {
+                merge(&mut preset_units, units.unwrap_or_default());
+                Some(preset_units)
+            }
+            None => units,
+        };
+
+        let mut problems = vec![];
+        let units_span = doc.get("units").and_then(|units| units.span());
+        let derive_g = units.is_some();
+        let unit_system =
+            match units.map(|units| ConfigUnits::deserialize(toml::Value::Table(units))) {
+                Some(Ok(units)) => match units.as_unit_system() {
+                    Ok(unit_system) => Some(unit_system),
+                    Err(err) => {
+                        problems.push(Problem {
+                            path: KeyPath::default(),
+                            message: err.to_string(),
+                            span: units_span,
+                        });
+                        None
+                    }
+                },
+                Some(Err(err)) => {
+                    problems.push(Problem {
+                        path: KeyPath::default().key("units"),
+                        message: err.message().to_owned(),
+                        span: units_span,
+                    });
+                    None
+                }
+                None => None,
+            };
+        let const_g = doc.get("global").and_then(|global| global.get("const_g"));
+        if let (true, Some(const_g)) = (derive_g, const_g) {
+            problems.push(Problem {
+                path: KeyPath::default().key("global").key("const_g"),
+                message: "comes from [units] when there's one, remove it".to_owned(),
+                span: const_g.span(),
+            });
+        }
+        let used_units = unit_system.unwrap_or_default();
+        // the overrides' `global` has to be known before anything's read
+        let overrides: toml::Table = options.overrides_source().parse().unwrap_or_default();
+        let overridden = |key| overrides.get("global").and_then(|global| global.get(key));
+        let seed_override = options
+            .overrides
+            .iter()
+            .rfind(|(key, _)| key == "global.seed")
+            .map(|(_, seed)| {
+                seed.parse::<u64>().map_err(|_| {
+                    fail(
+                        format!("--set global.seed: must be a whole number from 0 up, got {seed}"),
+                        None,
+                    )
+                })
+            })
+            .transpose()?;
+        let seed = options.seed.or(seed_override).unwrap_or(global.seed);
+        if seed > i64::MAX as u64 {
+            // it goes in `global.seed` and TOML integers are signed
+            return Err(fail(
+                format!("seed {seed} is over the most a config holds, {}", i64::MAX),
+                None,
+            ));
+        }
+        let mut replacements = units::convert(&doc, &used_units, &mut problems);
+        replacements.extend(random::resolve(&doc, &used_units, seed, &mut problems));
+        let converted = units::splice(source, replacements);
+        if !problems.is_empty() {
+            return Err(ConfigErrors::new(origin, Some(source), problems));
+        }
+
+        // same lines as `source`, only numbers with units and random
+        // ones changed
+        let mut config: Self = toml::from_str(&converted).map_err(|err| {
+            let problem = Problem {
+                path: KeyPath::default(),
+                message: err.message().to_owned(),
+                span: err.span(),
+            };
+            ConfigErrors::new(origin, Some(&converted), vec![problem])
+        })?;
+
+        // anything the config doesn't write back out wasn't used
+        if let Ok(known) = toml::Value::try_from(&config) {
+            unknown_keys(doc.as_item(), &known, &KeyPath::default(), &mut problems);
+        }
+
+        // paths in the file are relative to it
+        let (dir, chain) = match &options.path {
+            Some(path) => (
+                path.parent().unwrap_or(Path::new("")),
+                vec![path.canonicalize().unwrap_or_else(|_| path.clone())],
+            ),
+            None => (Path::new(""), vec![]),
+        };
+
+        // the preset, then what's included in order, then the file, then
+        // the overrides
+        let mut offsets = Offsets::new();
+        if preset.is_some() || !config.include.is_empty() || !options.overrides.is_empty() {
+            let mut merged = preset
+                .map(|preset| preset_table(preset, &used_units))
+                .unwrap_or_default();
+            let included = include::included(&doc, origin, dir, &used_units, seed, &chain)?;
+            merge(&mut merged, included);
+            let mut file: toml::Table =
+                toml::from_str(&converted).expect("parsed as a config just above");
+            file.remove("include");
+            include::resolve_imports(&mut file, dir);
+            offsets = merge(&mut merged, file);
+            let overrides = include::file_table(
+                &options.overrides_source(),
+                "--set",
+                Path::new(""),
+                &used_units,
+                Rng::derived(seed, "--set").next_u64(),
+                &[],
+            )?;
+            merge(&mut merged, overrides);
+            config = Self::deserialize(toml::Value::Table(merged))
+                .map_err(|err| fail(err.message().to_owned(), None))?;
+        } else {
+            for import in &mut config.import {
+                import.path = dir.join(&import.path);
+            }
+        }
+        // unless it's overridden, that's the point of overriding it
+        if let (Some(unit_system), None) = (unit_system, overridden("const_g")) {
+            config.global.const_g = unit_system.gravitational_constant();
+        }
+        config.global.seed = seed;
+
+        // after the config's own, so they're checked together
+        let written_imports = config.import.len();
+        config
+            .import
+            .extend(options.imports.iter().cloned().map(ConfigImport::new));
+        let span_of = |path: &KeyPath| match &path.0[..] {
+            // given on the command line, not anywhere in the file
+            [Segment::Key(key), Segment::Index(i)] if key == "import" && *i >= written_imports => {
+                None
+            }
+            _ => path.in_merged(&offsets).and_then(|path| path.span_in(&doc)),
+        };
+
+        // bodies that weren't written as such, and what they came from
+        let mut expanded = vec![];
+        let (first, count) = (config.bodies.len(), config.import.len());
+        let failed = config.take_imports();
+        let imported = (0..count).filter(|i| failed.iter().all(|(j, _)| j != i));
+        for (k, i) in imported.enumerate() {
+            expanded.push((
+                first + k..first + k + 1,
+                KeyPath::default().key("import").index(i),
+            ));
+        }
+        for (i, err) in failed {
+            let path = KeyPath::default().key("import").index(i);
+            problems.push(Problem {
+                span: span_of(&path),
+                path,
+                message: format!("{err:#}"),
+            });
+        }
+        for (i, generator) in std::mem::take(&mut config.generators)
+            .into_iter()
+            .enumerate()
+        {
+            let path = KeyPath::default().key("generators").index(i);
+            let rng = Rng::derived(seed, &path.to_string());
+            match generator.generate(&config.bodies, config.global.const_g, rng) {
+                Ok(bodies) => {
+                    let first = config.bodies.len();
+                    config.bodies.extend(bodies);
+                    expanded.push((first..config.bodies.len(), path));
+                }
+                Err(err) => problems.push(Problem {
+                    span: span_of(&path),
+                    path,
+                    message: format!("{err:#}"),
+                }),
+            }
+        }
+
+        let locate = |path: &KeyPath| {
+            let from = match &path.0[..] {
+                [Segment::Key(key), Segment::Index(i), ..] if key == "bodies" => {
+                    expanded.iter().find(|(bodies, _)| bodies.contains(i))
+                }
+                _ => None,
+            };
+            span_of(from.map_or(path, |(_, from)| from))
+        };
+        problems.extend(config.problems().into_iter().map(|problem| Problem {
+            span: locate(&problem.path),
+            ..problem
+        }));
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigErrors::new(origin, Some(source), problems))
+        }
+    }
+
+    /// Checks for values that would make the simulation fail or make
+    /// no sense, before anything gets built from them
+    pub fn validate(&self) -> Result<(), ConfigErrors> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors::new("config", None, problems))
+        }
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        let mut problems = Problems::default();
+        let root = KeyPath::default();
+
+        let global = root.key("global");
+        problems.finite(global.key("const_g"), self.global.const_g);
+        problems.positive(global.key("max_frame_dt"), self.global.max_frame_dt);
+
+        let camera = root.key("camera");
+        let (near, far) = self.camera.render_distance;
+        problems.check(
+            near > 0.0,
+            camera.key("render_distance"),
+            format!("near plane must be further than 0, got {near}"),
+        );
+        problems.check(
+            near < far,
+            camera.key("render_distance"),
+            format!("near plane ({near}) must be closer than the far one ({far})"),
+        );
+        problems.check(
+            self.camera.fov.0 > 0.0 && self.camera.fov.0 < 180.0,
+            camera.key("fov"),
+            format!(
+                "must be between 0 and 180 degrees, got {}",
+                self.camera.fov.0
+            ),
+        );
+        problems.check(
+            self.camera.position != self.camera.target,
+            camera.key("target"),
+            "camera can't look at where it is",
+        );
+
+        let physics = root.key("physics");
+        problems.non_negative(physics.key("dt"), self.physics.dt);
+        problems.check(
+            self.physics.dt == 0.0 || self.physics.max_steps_per_frame > 0,
+            physics.key("max_steps_per_frame"),
+            "must be at least 1 or the simulation never moves",
+        );
+        problems.positive(
+            physics.key("adaptive_tolerance"),
+            self.physics.adaptive_tolerance,
+        );
+        problems.non_negative(physics.key("theta"), self.physics.theta);
+        problems.non_negative(physics.key("softening"), self.physics.softening);
+        problems.check(
+            (0.0..=1.0).contains(&self.physics.restitution),
+            physics.key("restitution"),
+            format!("must be between 0 and 1, got {}", self.physics.restitution),
+        );
+        problems.non_negative(
+            physics.key("diagnostics_interval"),
+            self.physics.diagnostics_interval,
+        );
+        problems.finite(physics.key("start_time"), self.physics.start_time);
+
+        problems.non_negative(root.key("output").key("interval"), self.output.interval);
+        problems.finite(root.key("cheats").key("time_mult"), self.cheats.time_mult);
+
+        self.body_problems(&root.key("bodies"), &mut problems);
+        problems.0
+    }
+
+    fn body_problems(&self, bodies: &KeyPath, problems: &mut Problems) {
+        let errors_before = problems.0.len();
+        let mut names = HashMap::new();
+        for (i, body) in self.bodies.iter().enumerate() {
+            let path = bodies.index(i);
+            if let Some(first) = names.insert(&body.name, i) {
+                problems.push(
+                    path.key("name"),
+                    format!("{:?} is already the name of bodies[{first}]", body.name),
+                );
+            }
+            problems.positive(path.key("mass"), body.mass);
+            problems.positive(path.key("radius"), body.radius as f64);
+            for (key, v) in [("position", body.position), ("velocity", body.velocity)] {
+                problems.check(
+                    v.x.is_finite() && v.y.is_finite() && v.z.is_finite(),
+                    path.key(key),
+                    format!("must be finite, got {:?}", [v.x, v.y, v.z]),
+                );
+            }
+            if let Some(softening) = body.softening {
+                problems.non_negative(path.key("softening"), softening);
+            }
+            if let Some(orbit) = &body.orbit {
+                if let Err(err) = orbit.as_elements() {
+                    problems.push(path.key("orbit"), err.to_string());
+                }
+                if !self.bodies.iter().any(|other| other.name == orbit.parent) {
+                    problems.push(
+                        path.key("orbit").key("parent"),
+                        format!("no body named {:?}", orbit.parent),
+                    );
+                }
+            }
+        }
+
+        // only once every body makes sense on its own
+        if problems.0.len() > errors_before {
+            return;
+        }
+        let gbodies = match super::ConfigBody::as_gbodies(self.bodies.clone(), self.global.const_g)
+        {
+            Ok(gbodies) => gbodies,
+            Err(err) => return problems.push(bodies.clone(), err.to_string()),
+        };
+        for (i, j) in touching_pairs(&gbodies) {
+            let mode = CollisionMode::for_pair(
+                self.physics.collisions,
+                self.bodies[i].collision,
+                self.bodies[j].collision,
+            );
+            if mode == CollisionMode::Ignore {
+                continue;
+            }
+            // where it got its position from
+            let key = match self.bodies[j].orbit {
+                Some(_) => "orbit",
+                None => "position",
+            };
+            problems.push(
+                bodies.index(j).key(key),
+                format!(
+                    "{:?} overlaps {:?} (bodies[{i}]), {} apart with radii adding to {}",
+                    gbodies[j].name,
+                    gbodies[i].name,
+                    gbodies[i].pos.distance(gbodies[j].pos),
+                    gbodies[i].radius + gbodies[j].radius,
+                ),
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    fn push(&mut self, path: KeyPath, message: impl Into<String>) {
+        self.0.push(Problem {
+            path,
+            message: message.into(),
+            span: None,
+        });
+    }
+
+    fn check(&mut self, ok: bool, path: KeyPath, message: impl Into<String>) {
+        if !ok {
+            self.push(path, message);
+        }
+    }
+
+    fn finite(&mut self, path: KeyPath, value: f64) {
+        self.check(
+            value.is_finite(),
+            path,
+            format!("must be finite, got {value}"),
+        );
+    }
+
+    fn positive(&mut self, path: KeyPath, value: f64) {
+        // written so NaN fails too
+        self.check(
+            value > 0.0 && value.is_finite(),
+            path,
+            format!("must be positive, got {value}"),
+        );
+    }
+
+    fn non_negative(&mut self, path: KeyPath, value: f64) {
+        self.check(
+            value >= 0.0 && value.is_finite(),
+            path,
+            format!("can't be negative, got {value}"),
+        );
+    }
+}
+
+/// What has to be read before the rest of a config
+#[derive(Deserialize)]
+struct Head {
+    units: Option<toml::Table>,
+    preset: Option<Preset>,
+    #[serde(default)]
+    global: HeadGlobal,
+}
+
+#[derive(Deserialize, Default)]
+struct HeadGlobal {
+    #[serde(default)]
+    seed: u64,
+}
+
+/// `preset` with its quantities in `units`, to merge a config on top of
+fn preset_table(preset: Preset, units: &UnitSystem) -> toml::Table {
+    let source = preset.source();
+    let doc = toml_edit::ImDocument::parse(source).expect("bundled presets parse");
+    let mut problems = vec![];
+    let converted = units::splice(source, units::convert(&doc, units, &mut problems));
+    assert!(problems.is_empty(), "bundled {preset} has bad units");
+    toml::from_str(&converted).expect("bundled presets parse")
+}
+
+/// Reports keys in `item` that aren't in `known`, the config as it was
+/// understood written back out
+pub(super) fn unknown_keys(
+    item: &toml_edit::Item,
+    known: &toml::Value,
+    path: &KeyPath,
+    problems: &mut Vec<Problem>,
+) {
+    if let (Some(table), Some(known)) = (item.as_table_like(), known.as_table()) {
+        for (key, value) in table.iter() {
+            match known.get(key) {
+                Some(known) => unknown_keys(value, known, &path.key(key), problems),
+                None => problems.push(Problem {
+                    path: path.key(key),
+                    message: "unknown key".to_owned(),
+                    span: table.key(key).and_then(|key| key.span()),
+                }),
+            }
+        }
+    } else if let Some(known) = known.as_array() {
+        for (i, known) in known.iter().enumerate() {
+            let Some(value) = item.get(i) else { break };
+            unknown_keys(value, known, &path.index(i), problems);
+        }
+    }
+}
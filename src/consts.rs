@@ -1 +1 @@
-pub static GRAVITATIONAL_CONSTANT: f32 = 6.67e-11; // m³/(kg·s²) = mm³/(kg·ms²)
+pub static GRAVITATIONAL_CONSTANT: f64 = 6.67e-11; // m³/(kg·s²) = mm³/(kg·ms²)
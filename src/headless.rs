@@ -0,0 +1,61 @@
+use anyhow::bail;
+
+use crate::{
+    config::Config,
+    physics::{diagnostics::DiagnosticsLog, PhysicsMesh},
+};
+
+/// How long to run a simulation for
+#[derive(Debug, Clone, Copy)]
+pub enum RunLength {
+    /// Simulated time, rounded up to whole ticks
+    Duration(f64),
+    /// Physics ticks
+    Steps(u64),
+}
+
+/// Runs the simulation described by `config` without any window or GPU,
+/// in ticks of `physics.dt`, and returns the final state
+pub fn simulate(config: &Config, length: RunLength) -> anyhow::Result<PhysicsMesh> {
+    config.validate()?;
+    let dt = config.physics.dt;
+    if dt <= 0.0 {
+        bail!("headless runs need a fixed physics dt, got {dt}");
+    }
+
+    let mut pmesh = config
+        .physics
+        .as_physics_mesh(config.global.const_g, config.bodies.clone())?;
+    let mut diagnostics_log =
+        DiagnosticsLog::new(config.physics.diagnostics_interval).with_time_unit(config.time_unit());
+    diagnostics_log.update(&pmesh);
+    let mut recorder = config.output.as_recorder()?;
+    if let Some(recorder) = &mut recorder {
+        recorder.update(&pmesh)?;
+    }
+    let mut replay_writer = config.output.as_replay_writer(config)?;
+
+    let steps = match length {
+        RunLength::Duration(duration) => (duration / dt).ceil() as u64,
+        RunLength::Steps(steps) => steps,
+    };
+    for _ in 0..steps {
+        pmesh.compute(dt);
+        if let Some(replay_writer) = &mut replay_writer {
+            replay_writer.tick(dt);
+        }
+        diagnostics_log.update(&pmesh);
+        if let Some(recorder) = &mut recorder {
+            recorder.update(&pmesh)?;
+        }
+    }
+
+    if let Some(replay_writer) = &mut replay_writer {
+        replay_writer.flush()?;
+    }
+    if let Some(recorder) = &mut recorder {
+        recorder.flush()?;
+    }
+
+    Ok(pmesh)
+}
@@ -2,15 +2,43 @@
 
 pub mod config;
 pub mod consts;
+pub mod headless;
+pub mod overlay;
 pub mod physics;
+pub mod recorder;
+pub mod render;
+pub mod replay;
+pub mod snapshot;
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
 
-use physics::PhysicsMesh;
+use overlay::DebugOverlay;
+use physics::diagnostics::DiagnosticsLog;
+use render::BodyRenderer;
+use replay::{Replay, ReplayEvent, ReplayPlayer};
 
 use three_d::*;
 
 pub async fn run(config: config::Config) -> anyhow::Result<()> {
+    run_with(config, None).await
+}
+
+/// Plays `replay` back instead of simulating anew
+pub async fn run_replay(replay: Replay) -> anyhow::Result<()> {
+    run_with(replay.config, Some(replay.events)).await
+}
+
+async fn run_with(
+    config: config::Config,
+    replay_events: Option<Vec<ReplayEvent>>,
+) -> anyhow::Result<()> {
+    config.validate()?;
+    // what snapshots are taken on top of
+    let snapshot_base = config.clone();
+    let mut replay_writer = match replay_events {
+        Some(_) => None,
+        None => config.output.as_replay_writer(&config)?,
+    };
     let window = Window::new(WindowSettings {
         title: config.global.window_name,
         max_size: config.global.window_size,
@@ -19,17 +47,30 @@ pub async fn run(config: config::Config) -> anyhow::Result<()> {
     .unwrap();
     let context = window.gl();
 
+    let lock_to_barycenter = config.camera.lock_to_barycenter;
     let mut camera = config.camera.as_camera(window.viewport());
     let mut orbit_control = OrbitControl::new(*camera.target(), 1.0, 10000.0);
     let mut fly_control = FlyControl::new(0.01);
 
-    let mut pmesh = PhysicsMesh::default();
+    let mut pmesh = config
+        .physics
+        .as_physics_mesh(config.global.const_g, config.bodies)?;
+    let mut body_renderer = BodyRenderer::new(&context);
+    let mut timestep = config.physics.as_timestep();
+    let mut diagnostics_log = DiagnosticsLog::new(config.physics.diagnostics_interval)
+        .with_time_unit(snapshot_base.time_unit());
+    diagnostics_log.update(&pmesh);
+    let mut recorder = config.output.as_recorder()?;
+    if let Some(recorder) = &mut recorder {
+        recorder.update(&pmesh)?;
+    }
+    let mut player = replay_events.map(|events| ReplayPlayer::new(events, &pmesh));
 
-    config
-        .bodies
-        .into_iter()
-        .map(|body| body.as_gbody(&context).expect("error making body"))
-        .for_each(|gbody| pmesh.add(gbody));
+    let mut overlay =
+        DebugOverlay::new(&context, config.debug.overlay, config.debug.orbit_reference);
+    // a replay plays back what was done, nothing else
+    overlay.interventions = player.is_none();
+    overlay.time_unit = snapshot_base.time_unit().map(str::to_owned);
 
     let mut lights = config.lights.as_scene_lighting(&context); // .as_dyn_lights(&context);
 
@@ -37,29 +78,89 @@ pub async fn run(config: config::Config) -> anyhow::Result<()> {
 
     let clear_color_state = srgba_as_clearstate(config.global.background_color, 255);
     window.render_loop(move |mut frame_input| {
-        if frame_input.elapsed_time > config.global.max_frame_dt {
-            println!("dropping frame ({}ms)", frame_input.elapsed_time);
-            return FrameOutput::default();
-        }
         camera.set_viewport(frame_input.viewport);
+
+        let elapsed =
+            frame_input.elapsed_time.min(config.global.max_frame_dt) * config.cheats.time_mult;
+        let alpha = match &mut player {
+            Some(player) => {
+                if let Err(err) = player
+                    .handle_events(&mut frame_input.events, &mut pmesh)
+                    .and_then(|()| player.advance(&mut pmesh, elapsed))
+                {
+                    println!("replay stopped: {err}");
+                    player.paused = true;
+                }
+                // replays only go through the ticks' states
+                1.0
+            }
+            None => {
+                let alpha = timestep.advance_with(&mut pmesh, elapsed, |pmesh, dt| {
+                    if let Some(replay_writer) = &mut replay_writer {
+                        replay_writer.tick(dt);
+                    }
+                    let recorded = recorder.as_mut().map(|recorder| recorder.update(pmesh));
+                    if let Some(Err(err)) = recorded {
+                        println!("stopped recording: {err}");
+                        recorder = None;
+                    }
+                });
+                if let Some(Err(err)) = replay_writer
+                    .as_mut()
+                    .map(|replay_writer| replay_writer.flush())
+                {
+                    println!("stopped recording replay: {err}");
+                    replay_writer = None;
+                }
+                if let Some(Err(err)) = recorder.as_mut().map(|recorder| recorder.flush()) {
+                    println!("stopped recording: {err}");
+                    recorder = None;
+                }
+                alpha
+            }
+        };
+        diagnostics_log.update(&pmesh);
+
+        if lock_to_barycenter {
+            let target = pmesh.barycenter().0.map(|c| c as f32);
+            let offset = target - *camera.target();
+            camera.set_view(*camera.position() + offset, target, *camera.up());
+            orbit_control = OrbitControl::new(target, 1.0, 10000.0);
+        }
+        // before the camera controls, so they leave alone clicks on it
+        for intervention in overlay.update(&mut frame_input, &pmesh) {
+            let done = match &mut replay_writer {
+                Some(replay_writer) => replay_writer.intervene(&mut pmesh, intervention),
+                None => pmesh.intervene(&intervention),
+            };
+            if let Err(err) = done {
+                println!("{err}");
+            }
+        }
         orbit_control.handle_events(&mut camera, &mut frame_input.events);
         fly_control.handle_events(&mut camera, &mut frame_input.events);
+        if snapshot::requested(&mut frame_input.events) {
+            let snapshot = snapshot_base.snapshot(&pmesh, &camera);
+            match snapshot::save(&snapshot, snapshot_base.time_unit()) {
+                Ok(name) => println!("saved snapshot to {name}"),
+                Err(err) => println!("couldn't save snapshot: {err}"),
+            }
+        }
 
-        pmesh.compute((frame_input.elapsed_time * config.cheats.time_mult) as f32);
-
-        // so bcs we compute new body positions (`.render()`) after this
-        // the shadow compute will be a frame outdated, unless we call
-        // `.render()` twice...
-        pmesh.render();
-        let light_render = lights.render(
-            4096,
-            pmesh.get_mesh().as_slice(),
-        );
-        frame_input.screen().clear(clear_color_state).render(
-            &camera,
-            pmesh.render().as_slice().into_iter(),
-            light_render.as_slice(),
-        );
+        body_renderer.update(&pmesh, alpha);
+        let light_render = lights.render(4096, body_renderer.meshes().as_slice());
+        let screen = frame_input.screen();
+        let rendered = screen
+            .clear(clear_color_state)
+            .render(
+                &camera,
+                body_renderer.objects().as_slice().into_iter(),
+                light_render.as_slice(),
+            )
+            .write(|| overlay.render());
+        if let Err(err) = rendered {
+            println!("couldn't draw the overlay: {err}");
+        }
 
         FrameOutput::default()
     });
@@ -2,18 +2,60 @@
 
 pub mod args;
 
-use std::{fs::File, io::Read};
 use args::Args;
 use clap::Parser;
+use std::{fs::File, io::Read, path::Path};
+use threed_test::{
+    config::Config,
+    headless::RunLength,
+    replay::{self, Replay},
+};
 
 #[tokio::main]
 pub async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let mut conf_file = File::open(args.config)?;
-    let mut config = String::new();
-    conf_file.read_to_string(&mut config)?;
-    let config: threed_test::config::Config = toml::from_str(&config)?;
+    if let Some(path) = &args.replay {
+        let mut replay = Replay::load(path)?;
+        args.apply_to(&mut replay.config);
+        if args.headless {
+            let pmesh = replay::play_to_end(&replay)?;
+            return write_state(args.output.as_deref(), &replay.config.with_state(&pmesh));
+        }
+        return threed_test::run_replay(replay).await;
+    }
+
+    let options = args.load_options();
+    let mut config = match args.preset {
+        Some(preset) => Config::from_toml_with(preset.source(), &preset.to_string(), &options)?,
+        None => {
+            let mut conf_file = File::open(&args.config)?;
+            let mut config = String::new();
+            conf_file.read_to_string(&mut config)?;
+            Config::from_toml_with(&config, &args.config.display().to_string(), &options)?
+        }
+    };
+    args.apply_to(&mut config);
+
+    if args.headless {
+        let length = match (args.duration, args.steps) {
+            (Some(duration), _) => RunLength::Duration(duration),
+            (None, Some(steps)) => RunLength::Steps(steps),
+            (None, None) => unreachable!("clap requires one of them"),
+        };
+        let pmesh = threed_test::headless::simulate(&config, length)?;
+        return write_state(args.output.as_deref(), &config.with_state(&pmesh));
+    }
 
     threed_test::run(config).await
 }
+
+/// Final state of a headless run to `output`, stdout if not given
+fn write_state(output: Option<&Path>, state: &Config) -> anyhow::Result<()> {
+    let result = toml::to_string_pretty(state)?;
+    match output {
+        Some(path) => std::fs::write(path, result)?,
+        None => print!("{result}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use three_d::*;
+
+use crate::{physics::PhysicsMesh, replay::Intervention};
+
+const TOGGLE_KEY: Key = Key::F3;
+
+/// Window listing every body's osculating orbit, toggled with F3
+pub struct DebugOverlay {
+    gui: GUI,
+    pub visible: bool,
+    /// Buttons to stop or remove bodies, off while playing a replay back
+    pub interventions: bool,
+    /// Shown after the time, if it has one
+    pub time_unit: Option<String>,
+    /// Body all orbits are measured around, each body's dominant
+    /// attractor if unset or not found
+    pub orbit_reference: Option<String>,
+}
+
+impl DebugOverlay {
+    pub fn new(ctx: &Context, visible: bool, orbit_reference: Option<String>) -> Self {
+        Self {
+            gui: GUI::new(ctx),
+            visible,
+            interventions: true,
+            time_unit: None,
+            orbit_reference,
+        }
+    }
+
+    /// Returns what was asked to be done to the bodies through it
+    pub fn update(
+        &mut self,
+        frame_input: &mut FrameInput,
+        pmesh: &PhysicsMesh,
+    ) -> Vec<Intervention> {
+        for event in &mut frame_input.events {
+            if let Event::KeyPress {
+                kind: TOGGLE_KEY,
+                handled,
+                ..
+            } = event
+            {
+                if !*handled {
+                    self.visible = !self.visible;
+                    *handled = true;
+                }
+            }
+        }
+        if !self.visible {
+            return vec![];
+        }
+
+        let reference = self.orbit_reference.as_ref().and_then(|name| {
+            pmesh
+                .components
+                .iter()
+                .position(|gbody| &gbody.name == name)
+        });
+        let orbits = pmesh.osculating_elements(reference);
+        let mut interventions = vec![];
+
+        self.gui.update(
+            &mut frame_input.events,
+            frame_input.accumulated_time,
+            frame_input.viewport,
+            frame_input.device_pixel_ratio,
+            |gui_context| {
+                egui::Window::new("Orbits").show(gui_context, |ui| {
+                    let unit = self.time_unit.as_ref().map(|unit| format!(" {unit}"));
+                    ui.label(format!("t = {:.1}{}", pmesh.time, unit.unwrap_or_default()));
+                    egui::Grid::new("orbits").striped(true).show(ui, |ui| {
+                        for header in [
+                            "body",
+                            "around",
+                            "a",
+                            "e",
+                            "i",
+                            "period",
+                            "periapsis",
+                            "apoapsis",
+                            "",
+                        ] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+
+                        for (gbody, orbit) in pmesh.components.iter().zip(&orbits) {
+                            ui.label(&gbody.name);
+                            match orbit {
+                                Some(orbit) => {
+                                    let maybe = |value: Option<f64>| {
+                                        value.map_or(String::from("-"), |value| {
+                                            format!("{value:.4e}")
+                                        })
+                                    };
+                                    ui.label(&pmesh.components[orbit.reference].name);
+                                    ui.label(format!("{:.4e}", orbit.elements.semi_major_axis));
+                                    ui.label(format!("{:.4}", orbit.elements.eccentricity));
+                                    ui.label(format!(
+                                        "{:.2}°",
+                                        orbit.elements.inclination.to_degrees()
+                                    ));
+                                    ui.label(maybe(orbit.period));
+                                    ui.label(format!("{:.4e}", orbit.periapsis));
+                                    ui.label(maybe(orbit.apoapsis));
+                                }
+                                None => {
+                                    for _ in 0..7 {
+                                        ui.label("-");
+                                    }
+                                }
+                            }
+                            if self.interventions {
+                                ui.horizontal(|ui| {
+                                    if ui.small_button("stop").clicked() {
+                                        interventions.push(Intervention::SetMotion {
+                                            body: gbody.name.clone(),
+                                            pos: gbody.pos.into(),
+                                            vel: [0.0; 3],
+                                        });
+                                    }
+                                    if ui.small_button("remove").clicked() {
+                                        interventions.push(Intervention::Remove {
+                                            body: gbody.name.clone(),
+                                        });
+                                    }
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            },
+        );
+        interventions
+    }
+
+    pub fn render(&mut self) -> Result<(), CoreError> {
+        if !self.visible {
+            return Ok(());
+        }
+        self.gui.render()
+    }
+}
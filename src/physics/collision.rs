@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use three_d::*;
+
+use super::GBody;
+
+/// What happens when two bodies' spheres touch
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollisionMode {
+    /// They go through each other
+    Ignore,
+    /// They bounce off each other, see `Collisions::restitution`
+    Bounce,
+    /// They become a single body with their combined mass and momentum,
+    /// volume adds up and color is blended by mass
+    Merge,
+}
+
+impl CollisionMode {
+    /// A body's own mode beats the global one, when both bodies of a pair
+    /// have theirs the least destructive one is used
+    pub(crate) fn for_pair(global: Self, a: Option<Self>, b: Option<Self>) -> Self {
+        match (a, b) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(mode), None) | (None, Some(mode)) => mode,
+            (None, None) => global,
+        }
+    }
+}
+
+pub struct Collisions {
+    pub mode: CollisionMode,
+    /// Fraction of the approaching speed kept after bouncing, `1` is a
+    /// perfectly elastic collision and `0` makes them stick together
+    pub restitution: f64,
+}
+
+impl Default for Collisions {
+    fn default() -> Self {
+        Self {
+            mode: CollisionMode::Ignore,
+            restitution: 1.0,
+        }
+    }
+}
+
+impl Collisions {
+    pub fn new(mode: CollisionMode, restitution: f64) -> Self {
+        Self { mode, restitution }
+    }
+
+    /// Finds touching pairs and applies their collision mode to them,
+    /// merged bodies are removed from `bodies`
+    pub fn resolve(&self, bodies: &mut Vec<GBody>) {
+        if self.mode == CollisionMode::Ignore
+            && bodies.iter().all(|gbody| gbody.collision.is_none())
+        {
+            return;
+        }
+
+        let mut merged = vec![false; bodies.len()];
+        for (i, j) in touching_pairs(bodies) {
+            if merged[i] || merged[j] {
+                continue;
+            }
+
+            let mode = CollisionMode::for_pair(self.mode, bodies[i].collision, bodies[j].collision);
+            match mode {
+                CollisionMode::Ignore => {}
+                CollisionMode::Bounce => {
+                    let (a, b) = pair_mut(bodies, i, j);
+                    bounce(a, b, self.restitution);
+                }
+                CollisionMode::Merge => {
+                    // the heavier one survives, keeping its name
+                    let (into, from) = if bodies[i].mass >= bodies[j].mass {
+                        (i, j)
+                    } else {
+                        (j, i)
+                    };
+                    let (a, b) = pair_mut(bodies, into, from);
+                    merge(a, b);
+                    merged[from] = true;
+                }
+            }
+        }
+
+        if merged.contains(&true) {
+            let mut merged = merged.into_iter();
+            bodies.retain(|_| !merged.next().unwrap());
+        }
+    }
+}
+
+/// Sweep and prune along x, pairs come out sorted so resolution order
+/// doesn't depend on anything but the bodies
+pub(crate) fn touching_pairs(bodies: &[GBody]) -> Vec<(usize, usize)> {
+    let mut order: Vec<_> = (0..bodies.len()).collect();
+    order.sort_by(|&a, &b| {
+        let start = |i: usize| bodies[i].pos.x - bodies[i].radius as f64;
+        start(a).total_cmp(&start(b))
+    });
+
+    let mut pairs = vec![];
+    for (k, &i) in order.iter().enumerate() {
+        let end = bodies[i].pos.x + bodies[i].radius as f64;
+        for &j in &order[k + 1..] {
+            if bodies[j].pos.x - bodies[j].radius as f64 > end {
+                break;
+            }
+
+            let reach = (bodies[i].radius + bodies[j].radius) as f64;
+            if bodies[i].pos.distance2(bodies[j].pos) < reach * reach {
+                pairs.push((i.min(j), i.max(j)));
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+fn pair_mut(bodies: &mut [GBody], i: usize, j: usize) -> (&mut GBody, &mut GBody) {
+    if i < j {
+        let (left, right) = bodies.split_at_mut(j);
+        (&mut left[i], &mut right[0])
+    } else {
+        let (left, right) = bodies.split_at_mut(i);
+        (&mut right[0], &mut left[j])
+    }
+}
+
+fn bounce(a: &mut GBody, b: &mut GBody, restitution: f64) {
+    let d = b.pos - a.pos;
+    let distance = d.magnitude();
+    if distance == 0.0 {
+        return;
+    }
+    let normal = d / distance;
+    let inv_mass_sum = 1.0 / a.mass + 1.0 / b.mass;
+
+    // impulse along the normal, only if they're still getting closer
+    let approaching = (b.vel - a.vel).dot(normal);
+    if approaching < 0.0 {
+        let impulse = -(1.0 + restitution) * approaching / inv_mass_sum;
+        a.accelerate(normal * (-impulse / a.mass));
+        b.accelerate(normal * (impulse / b.mass));
+    }
+
+    // and push them apart so they don't stay stuck inside each other
+    let overlap = (a.radius + b.radius) as f64 - distance;
+    a.displace(normal * (-overlap * (1.0 / a.mass) / inv_mass_sum));
+    b.displace(normal * (overlap * (1.0 / b.mass) / inv_mass_sum));
+}
+
+fn merge(into: &mut GBody, from: &GBody) {
+    let mass = into.mass + from.mass;
+    let weigh = |a: Vector3<f64>, b: Vector3<f64>| (a * into.mass + b * from.mass) / mass;
+    let pos = weigh(into.pos, from.pos);
+    let prev_pos = weigh(into.prev_pos, from.prev_pos);
+    let vel = weigh(into.vel, from.vel);
+
+    let blend = |a: u8, b: u8| ((a as f64 * into.mass + b as f64 * from.mass) / mass).round() as u8;
+    into.color = Srgba::new(
+        blend(into.color.r, from.color.r),
+        blend(into.color.g, from.color.g),
+        blend(into.color.b, from.color.b),
+        blend(into.color.a, from.color.a),
+    );
+    into.radius = (into.radius.powi(3) + from.radius.powi(3)).cbrt();
+
+    into.set_motion(pos, vel);
+    into.prev_pos = prev_pos;
+    into.mass = mass;
+}
@@ -0,0 +1,193 @@
+use three_d::*;
+
+use super::PhysicsMesh;
+
+/// Conserved quantities of the whole system at some point in time, all
+/// of them should stay put for an isolated system, bar merges' lost
+/// kinetic energy
+#[derive(Debug, Clone, Copy)]
+pub struct Diagnostics {
+    pub time: f64,
+    pub kinetic_energy: f64,
+    pub potential_energy: f64,
+    pub momentum: Vector3<f64>,
+    /// Around the origin
+    pub angular_momentum: Vector3<f64>,
+    pub barycenter: Vector3<f64>,
+    pub barycenter_velocity: Vector3<f64>,
+    /// Σ m·|v|, what momentum drift is measured against, as the total can
+    /// be (and ideally is) zero
+    pub momentum_scale: f64,
+    /// Σ m·|r × v|, same idea
+    pub angular_momentum_scale: f64,
+}
+
+/// How much each conserved quantity changed relative to a reference
+#[derive(Debug, Clone, Copy)]
+pub struct Drift {
+    pub energy: f64,
+    pub momentum: f64,
+    pub angular_momentum: f64,
+}
+
+impl Diagnostics {
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    pub fn drift_from(&self, initial: &Diagnostics) -> Drift {
+        let relative = |change: f64, scale: f64| if scale == 0.0 { change } else { change / scale };
+        Drift {
+            energy: relative(
+                self.total_energy() - initial.total_energy(),
+                initial.total_energy().abs(),
+            ),
+            momentum: relative(
+                (self.momentum - initial.momentum).magnitude(),
+                initial.momentum_scale,
+            ),
+            angular_momentum: relative(
+                (self.angular_momentum - initial.angular_momentum).magnitude(),
+                initial.angular_momentum_scale,
+            ),
+        }
+    }
+}
+
+impl PhysicsMesh {
+    pub fn kinetic_energy(&self) -> f64 {
+        self.components
+            .iter()
+            .map(|gbody| 0.5 * gbody.mass * gbody.vel.magnitude2())
+            .sum()
+    }
+
+    pub fn potential_energy(&self) -> f64 {
+        self.gravity.potential_energy(&self.components)
+    }
+
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy() + self.potential_energy()
+    }
+
+    pub fn total_mass(&self) -> f64 {
+        self.components.iter().map(|gbody| gbody.mass).sum()
+    }
+
+    pub fn momentum(&self) -> Vector3<f64> {
+        self.components
+            .iter()
+            .fold(Vector3::zero(), |p, gbody| p + gbody.vel * gbody.mass)
+    }
+
+    pub fn angular_momentum(&self) -> Vector3<f64> {
+        self.components.iter().fold(Vector3::zero(), |l, gbody| {
+            l + gbody.pos.cross(gbody.vel * gbody.mass)
+        })
+    }
+
+    /// Position and velocity of the center of mass
+    pub fn barycenter(&self) -> (Vector3<f64>, Vector3<f64>) {
+        let mass = self.total_mass();
+        if mass == 0.0 {
+            return (Vector3::zero(), Vector3::zero());
+        }
+
+        let weighted_pos = self
+            .components
+            .iter()
+            .fold(Vector3::zero(), |acc, gbody| acc + gbody.pos * gbody.mass);
+        (weighted_pos / mass, self.momentum() / mass)
+    }
+
+    /// Moves everything so the barycenter sits at the origin at rest,
+    /// getting rid of any net drift of the whole system
+    pub fn to_barycentric_frame(&mut self) {
+        let (barycenter, barycenter_velocity) = self.barycenter();
+        self.components.iter_mut().for_each(|gbody| {
+            gbody.set_motion(gbody.pos - barycenter, gbody.vel - barycenter_velocity)
+        });
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        let (barycenter, barycenter_velocity) = self.barycenter();
+        Diagnostics {
+            time: self.time,
+            kinetic_energy: self.kinetic_energy(),
+            potential_energy: self.potential_energy(),
+            momentum: self.momentum(),
+            angular_momentum: self.angular_momentum(),
+            barycenter,
+            barycenter_velocity,
+            momentum_scale: self
+                .components
+                .iter()
+                .map(|gbody| gbody.mass * gbody.vel.magnitude())
+                .sum(),
+            angular_momentum_scale: self
+                .components
+                .iter()
+                .map(|gbody| gbody.mass * gbody.pos.cross(gbody.vel).magnitude())
+                .sum(),
+        }
+    }
+}
+
+/// Prints the diagnostics and their drift from the first ones every
+/// `interval` of simulated time
+pub struct DiagnosticsLog {
+    pub interval: f64,
+    /// Printed after times, if they have one
+    pub time_unit: Option<String>,
+    initial: Option<Diagnostics>,
+    next_log: f64,
+}
+
+impl DiagnosticsLog {
+    /// An `interval` of `0` never logs
+    pub fn new(interval: f64) -> Self {
+        Self {
+            interval,
+            time_unit: None,
+            initial: None,
+            next_log: 0.0,
+        }
+    }
+
+    pub fn with_time_unit(mut self, time_unit: Option<&str>) -> Self {
+        self.time_unit = time_unit.map(str::to_owned);
+        self
+    }
+
+    pub fn initial(&self) -> Option<&Diagnostics> {
+        self.initial.as_ref()
+    }
+
+    pub fn update(&mut self, pmesh: &PhysicsMesh) {
+        if self.interval <= 0.0 || pmesh.time < self.next_log {
+            return;
+        }
+        self.next_log = pmesh.time + self.interval;
+
+        let diagnostics = pmesh.diagnostics();
+        let initial = *self.initial.get_or_insert(diagnostics);
+        let drift = diagnostics.drift_from(&initial);
+        let unit = self.time_unit.as_ref().map(|unit| format!(" {unit}"));
+        let unit = unit.unwrap_or_default();
+        println!(
+            "t={:.1}{unit} E={:e} (K={:e} U={:e}) p={:?} L={:?} barycenter={:?} v={:?}",
+            diagnostics.time,
+            diagnostics.total_energy(),
+            diagnostics.kinetic_energy,
+            diagnostics.potential_energy,
+            diagnostics.momentum,
+            diagnostics.angular_momentum,
+            diagnostics.barycenter,
+            diagnostics.barycenter_velocity,
+        );
+        println!(
+            "  drift: ΔE/E={:e} Δp={:e} ΔL={:e}",
+            drift.energy, drift.momentum, drift.angular_momentum
+        );
+    }
+}
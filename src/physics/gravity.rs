@@ -0,0 +1,199 @@
+use three_d::*;
+
+use super::{octree::Octree, GBody};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Solver {
+    /// Every body against every other, exact but O(N²)
+    Direct,
+    /// Far away groups of bodies are lumped into their center of mass,
+    /// `theta` is the opening angle (cell size / distance) under which
+    /// a group is considered far enough, `0` degenerates into direct sum
+    BarnesHut { theta: f64 },
+}
+
+/// Below this many bodies splitting the work costs more than it saves
+#[cfg(not(target_arch = "wasm32"))]
+const MIN_PARALLEL_BODIES: usize = 256;
+
+pub struct Gravity {
+    pub const_g: f64,
+    pub solver: Solver,
+    /// Plummer softening length for bodies without their own
+    pub softening: f64,
+    /// Forces are computed on the calling thread without it
+    #[cfg(not(target_arch = "wasm32"))]
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self::new(crate::consts::GRAVITATIONAL_CONSTANT, Solver::Direct)
+    }
+}
+
+/// Acceleration `mass` at `to` causes on something at `from`, Plummer
+/// softened by `softening_sq` (ε²) so it peaks instead of diverging as
+/// they get closer.
+///
+/// Coincident positions have no direction to pull towards, so they exert
+/// no force on each other. Softened this is also the limit the force
+/// tends to, unsoftened it's just the only sensible answer.
+pub(super) fn pair_accel(
+    const_g: f64,
+    from: Vector3<f64>,
+    to: Vector3<f64>,
+    mass: f64,
+    softening_sq: f64,
+) -> Vector3<f64> {
+    if from == to {
+        return Vector3::zero();
+    }
+
+    let dr = to - from;
+    let distance_sq = dr.magnitude2() + softening_sq;
+    dr * (const_g * mass / (distance_sq * distance_sq.sqrt()))
+}
+
+impl Gravity {
+    pub fn new(const_g: f64, solver: Solver) -> Self {
+        Self {
+            const_g,
+            solver,
+            softening: 0.0,
+            #[cfg(not(target_arch = "wasm32"))]
+            pool: None,
+        }
+    }
+
+    pub fn with_softening(mut self, softening: f64) -> Self {
+        self.softening = softening;
+        self
+    }
+
+    /// Spreads force computation over `threads` threads, `0` for one per
+    /// core and `1` to keep everything on the calling thread. Each body's
+    /// acceleration is still summed in the same order by a single thread
+    /// so results don't depend on the thread count.
+    ///
+    /// No-op on wasm, no threads there.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.pool = match threads {
+                1 => None,
+                _ => rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|i| format!("gravity-{i}"))
+                    .build()
+                    .inspect_err(|err| println!("couldn't start physics threads: {err}"))
+                    .ok(),
+            };
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = threads;
+        self
+    }
+
+    /// Fills `out` with the gravitational acceleration each body feels
+    /// at its current position, indexed the same as `bodies`
+    pub fn accelerations(&self, bodies: &[GBody], out: &mut Vec<Vector3<f64>>) {
+        self.accelerations_with(bodies, |i| bodies[i].pos, out);
+    }
+
+    /// Same as [`Gravity::accelerations`] but pretending the bodies are
+    /// at `positions` instead, for integrators probing trial states
+    pub fn accelerations_at(
+        &self,
+        bodies: &[GBody],
+        positions: &[Vector3<f64>],
+        out: &mut Vec<Vector3<f64>>,
+    ) {
+        self.accelerations_with(bodies, |i| positions[i], out);
+    }
+
+    fn accelerations_with(
+        &self,
+        bodies: &[GBody],
+        pos: impl Fn(usize) -> Vector3<f64>,
+        out: &mut Vec<Vector3<f64>>,
+    ) {
+        // flat copies, `pos` may not be where the bodies are and the
+        // octree is built over plain slices
+        let positions: Vec<_> = (0..bodies.len()).map(pos).collect();
+        let masses: Vec<_> = bodies.iter().map(|gbody| gbody.mass).collect();
+        let softenings_sq: Vec<_> = bodies
+            .iter()
+            .map(|gbody| gbody.softening.unwrap_or(self.softening).powi(2))
+            .collect();
+
+        match self.solver {
+            Solver::Direct => self.for_each_body(out, positions.len(), |i| {
+                positions.iter().zip(&masses).zip(&softenings_sq).fold(
+                    Vector3::zero(),
+                    |acc_accel, ((to, mass), softening_sq)| {
+                        // symmetric so the pair still pulls equally on each other
+                        let pair_softening_sq = (softenings_sq[i] + softening_sq) / 2.0;
+                        acc_accel
+                            + pair_accel(self.const_g, positions[i], *to, *mass, pair_softening_sq)
+                    },
+                )
+            }),
+            Solver::BarnesHut { theta } => {
+                let tree = Octree::new(&positions, &masses);
+                self.for_each_body(out, positions.len(), |i| {
+                    tree.acceleration(i, theta, self.const_g, &softenings_sq)
+                });
+            }
+        }
+    }
+
+    /// Total gravitational potential energy, softened the same way
+    /// forces are, coincident pairs included (they do have energy)
+    pub fn potential_energy(&self, bodies: &[GBody]) -> f64 {
+        let softening_sq = |gbody: &GBody| gbody.softening.unwrap_or(self.softening).powi(2);
+
+        let mut energy = 0.0;
+        for (i, a) in bodies.iter().enumerate() {
+            for b in &bodies[i + 1..] {
+                let distance_sq =
+                    a.pos.distance2(b.pos) + (softening_sq(a) + softening_sq(b)) / 2.0;
+                if distance_sq > 0.0 {
+                    energy -= self.const_g * a.mass * b.mass / distance_sq.sqrt();
+                }
+            }
+        }
+        energy
+    }
+
+    /// `out[i] = accel(i)` for all `len` bodies, in parallel if possible
+    fn for_each_body(
+        &self,
+        out: &mut Vec<Vector3<f64>>,
+        len: usize,
+        accel: impl Fn(usize) -> Vector3<f64> + Sync,
+    ) {
+        out.clear();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(pool) = self.pool.as_ref().filter(|_| len >= MIN_PARALLEL_BODIES) {
+            use rayon::prelude::*;
+
+            out.resize(len, Vector3::zero());
+            let chunk_len = len.div_ceil(pool.current_num_threads() * 4);
+            pool.install(|| {
+                out.par_chunks_mut(chunk_len)
+                    .enumerate()
+                    .for_each(|(chunk, accels)| {
+                        let start = chunk * chunk_len;
+                        for (i, a) in accels.iter_mut().enumerate() {
+                            *a = accel(start + i);
+                        }
+                    })
+            });
+            return;
+        }
+
+        out.extend((0..len).map(accel));
+    }
+}
@@ -0,0 +1,378 @@
+use three_d::*;
+
+use super::{gravity::Gravity, GBody};
+
+/// Advances the bodies' positions and velocities by `dt`, asking
+/// `gravity` for accelerations as many times as the scheme needs
+pub trait Integrator: BoxedIntegrator {
+    fn step(&mut self, bodies: &mut [GBody], gravity: &Gravity, dt: f64);
+}
+
+/// Clones a `Box<dyn Integrator>` along with whatever it carries between
+/// steps, so replays can go back to an earlier state and redo it exactly
+pub trait BoxedIntegrator {
+    fn boxed_clone(&self) -> Box<dyn Integrator>;
+}
+
+impl<T: Integrator + Clone + 'static> BoxedIntegrator for T {
+    fn boxed_clone(&self) -> Box<dyn Integrator> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Integrator> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+fn kick(bodies: &mut [GBody], accels: &[Vector3<f64>], dt: f64) {
+    bodies
+        .iter_mut()
+        .zip(accels)
+        .for_each(|(gbody, accel)| gbody.accelerate(accel * dt));
+}
+
+fn drift(bodies: &mut [GBody], dt: f64) {
+    bodies.iter_mut().for_each(|gbody| gbody.process(dt));
+}
+
+/// The original scheme, kick with the current acceleration and then
+/// drift with the new velocity. Cheap but energy drifts over long runs.
+#[derive(Default, Clone)]
+pub struct SemiImplicitEuler {
+    accels: Vec<Vector3<f64>>,
+}
+
+impl Integrator for SemiImplicitEuler {
+    fn step(&mut self, bodies: &mut [GBody], gravity: &Gravity, dt: f64) {
+        gravity.accelerations(bodies, &mut self.accels);
+        kick(bodies, &self.accels, dt);
+        drift(bodies, dt);
+    }
+}
+
+/// x₁ = x₀ + v₀·dt + ½·a₀·dt², v₁ = v₀ + ½·(a₀ + a₁)·dt
+#[derive(Default, Clone)]
+pub struct VelocityVerlet {
+    accels: Vec<Vector3<f64>>,
+    new_accels: Vec<Vector3<f64>>,
+}
+
+impl Integrator for VelocityVerlet {
+    fn step(&mut self, bodies: &mut [GBody], gravity: &Gravity, dt: f64) {
+        gravity.accelerations(bodies, &mut self.accels);
+        bodies
+            .iter_mut()
+            .zip(&self.accels)
+            .for_each(|(gbody, accel)| gbody.displace(gbody.vel * dt + accel * (0.5 * dt * dt)));
+
+        gravity.accelerations(bodies, &mut self.new_accels);
+        bodies
+            .iter_mut()
+            .zip(self.accels.iter().zip(&self.new_accels))
+            .for_each(|(gbody, (a0, a1))| gbody.accelerate((a0 + a1) * (0.5 * dt)));
+    }
+}
+
+/// Kick-drift-kick leapfrog, half a kick, a full drift and the other half
+#[derive(Default, Clone)]
+pub struct LeapfrogKDK {
+    accels: Vec<Vector3<f64>>,
+}
+
+impl Integrator for LeapfrogKDK {
+    fn step(&mut self, bodies: &mut [GBody], gravity: &Gravity, dt: f64) {
+        gravity.accelerations(bodies, &mut self.accels);
+        kick(bodies, &self.accels, dt / 2.0);
+        drift(bodies, dt);
+        gravity.accelerations(bodies, &mut self.accels);
+        kick(bodies, &self.accels, dt / 2.0);
+    }
+}
+
+/// Yoshida's 4th order composition of three leapfrog steps, the middle
+/// one going backwards in time
+#[derive(Default, Clone)]
+pub struct Yoshida4 {
+    accels: Vec<Vector3<f64>>,
+}
+
+impl Yoshida4 {
+    const CBRT_2: f64 = 1.259_921_049_894_873_2;
+    const W1: f64 = 1.0 / (2.0 - Self::CBRT_2);
+    const W0: f64 = -Self::CBRT_2 / (2.0 - Self::CBRT_2);
+
+    const DRIFTS: [f64; 4] = [
+        Self::W1 / 2.0,
+        (Self::W0 + Self::W1) / 2.0,
+        (Self::W0 + Self::W1) / 2.0,
+        Self::W1 / 2.0,
+    ];
+    const KICKS: [f64; 3] = [Self::W1, Self::W0, Self::W1];
+}
+
+impl Integrator for Yoshida4 {
+    fn step(&mut self, bodies: &mut [GBody], gravity: &Gravity, dt: f64) {
+        for i in 0..Self::KICKS.len() {
+            drift(bodies, Self::DRIFTS[i] * dt);
+            gravity.accelerations(bodies, &mut self.accels);
+            kick(bodies, &self.accels, Self::KICKS[i] * dt);
+        }
+        drift(bodies, Self::DRIFTS[3] * dt);
+    }
+}
+
+/// Embedded Runge-Kutta 5(4) with error control. A frame's `dt` is split
+/// into as many substeps as needed to keep the estimated local error of
+/// every position and velocity component under `tolerance` (mixed
+/// absolute/relative), up to `max_substeps` after which the rest of the
+/// frame is taken in one go.
+#[derive(Clone)]
+pub struct DormandPrince {
+    pub tolerance: f64,
+    pub max_substeps: u32,
+    /// Substeps used by the last call to `step`
+    pub last_substeps: u32,
+    /// Step size that worked last time, carried over between frames
+    h: Option<f64>,
+    kx: [Vec<Vector3<f64>>; 7],
+    kv: [Vec<Vector3<f64>>; 7],
+    stage_pos: Vec<Vector3<f64>>,
+}
+
+impl DormandPrince {
+    const A: [[f64; 6]; 7] = [
+        [0.0; 6],
+        [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+        [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+        [
+            19372.0 / 6561.0,
+            -25360.0 / 2187.0,
+            64448.0 / 6561.0,
+            -212.0 / 729.0,
+            0.0,
+            0.0,
+        ],
+        [
+            9017.0 / 3168.0,
+            -355.0 / 33.0,
+            46732.0 / 5247.0,
+            49.0 / 176.0,
+            -5103.0 / 18656.0,
+            0.0,
+        ],
+        // 5th order solution, its derivative is the 7th stage (FSAL)
+        [
+            35.0 / 384.0,
+            0.0,
+            500.0 / 1113.0,
+            125.0 / 192.0,
+            -2187.0 / 6784.0,
+            11.0 / 84.0,
+        ],
+    ];
+    /// 5th order minus 4th order weights
+    const E: [f64; 7] = [
+        71.0 / 57600.0,
+        0.0,
+        -71.0 / 16695.0,
+        71.0 / 1920.0,
+        -17253.0 / 339200.0,
+        22.0 / 525.0,
+        -1.0 / 40.0,
+    ];
+
+    const SAFETY: f64 = 0.9;
+    const MIN_SCALE: f64 = 0.2;
+    const MAX_SCALE: f64 = 5.0;
+
+    pub fn new(tolerance: f64, max_substeps: u32) -> Self {
+        Self {
+            tolerance,
+            max_substeps,
+            last_substeps: 0,
+            h: None,
+            kx: Default::default(),
+            kv: Default::default(),
+            stage_pos: vec![],
+        }
+    }
+
+    /// Fills stages 2 to 7 from `h` and the already computed first stage,
+    /// returns the error norm, ≤ 1 meaning the step is acceptable
+    fn attempt(&mut self, bodies: &[GBody], gravity: &Gravity, h: f64) -> f64 {
+        for s in 1..7 {
+            let mut stage_vel = Vec::with_capacity(bodies.len());
+            self.stage_pos.clear();
+            for (i, gbody) in bodies.iter().enumerate() {
+                let (mut dx, mut dv) = (Vector3::zero(), Vector3::zero());
+                for (j, a) in Self::A[s][..s].iter().enumerate() {
+                    dx += self.kx[j][i] * *a;
+                    dv += self.kv[j][i] * *a;
+                }
+                self.stage_pos.push(gbody.pos + dx * h);
+                stage_vel.push(gbody.vel + dv * h);
+            }
+            self.kx[s] = stage_vel;
+            gravity.accelerations_at(bodies, &self.stage_pos, &mut self.kv[s]);
+        }
+
+        let mut err: f64 = 0.0;
+        for (i, gbody) in bodies.iter().enumerate() {
+            let (mut ex, mut ev) = (Vector3::zero(), Vector3::zero());
+            for (s, e) in Self::E.iter().enumerate() {
+                ex += self.kx[s][i] * *e;
+                ev += self.kv[s][i] * *e;
+            }
+            // stage 7 was evaluated at the 5th order solution
+            let (new_pos, new_vel) = (self.stage_pos[i], self.kx[6][i]);
+            for (e, y0, y1) in [(ex * h, gbody.pos, new_pos), (ev * h, gbody.vel, new_vel)] {
+                for c in 0..3 {
+                    let scale = self.tolerance * (1.0 + y0[c].abs().max(y1[c].abs()));
+                    err = err.max(e[c].abs() / scale);
+                }
+            }
+        }
+        err
+    }
+}
+
+impl Integrator for DormandPrince {
+    fn step(&mut self, bodies: &mut [GBody], gravity: &Gravity, dt: f64) {
+        self.last_substeps = 0;
+        if dt <= 0.0 || bodies.is_empty() {
+            return;
+        }
+
+        self.kx[0] = bodies.iter().map(|gbody| gbody.vel).collect();
+        gravity.accelerations(bodies, &mut self.kv[0]);
+
+        let mut remaining = dt;
+        let mut h = self.h.unwrap_or(dt);
+        while remaining > 0.0 {
+            let forced = self.last_substeps + 1 >= self.max_substeps;
+            let step = if forced { remaining } else { h.min(remaining) };
+
+            let err = self.attempt(bodies, gravity, step);
+            let scale = if err == 0.0 {
+                Self::MAX_SCALE
+            } else if err.is_finite() {
+                (Self::SAFETY * err.powf(-0.2)).clamp(Self::MIN_SCALE, Self::MAX_SCALE)
+            } else {
+                Self::MIN_SCALE
+            };
+
+            if err <= 1.0 || forced {
+                if err > 1.0 {
+                    println!(
+                        "rk45 ran out of substeps ({}), error estimate {err}",
+                        self.max_substeps
+                    );
+                }
+                // not `set_motion`, `prev_pos` has to stay where the tick
+                // started for rendering to interpolate from it
+                for (i, gbody) in bodies.iter_mut().enumerate() {
+                    gbody.pos = self.stage_pos[i];
+                    gbody.vel = self.kx[6][i];
+                }
+                remaining -= step;
+                self.last_substeps += 1;
+                // first same as last, no need to evaluate it again
+                self.kx.swap(0, 6);
+                self.kv.swap(0, 6);
+
+                // a step clipped to fit the end of the frame says
+                // nothing bad about `h`, don't shrink it for that
+                h = if step < h {
+                    h.max(step * scale)
+                } else {
+                    step * scale
+                };
+            } else {
+                h = step * scale;
+            }
+        }
+        self.h = Some(h);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::TAU;
+
+    use super::*;
+    use crate::physics::{gravity::Solver, PhysicsMesh};
+
+    /// A light body on an `eccentricity` orbit of semi-major axis 1
+    /// around a heavy one, starting at periapsis, G·M = 1 so a period
+    /// is 2π
+    fn kepler(integrator: impl Integrator + 'static, eccentricity: f64) -> PhysicsMesh {
+        let mut pmesh = PhysicsMesh::new(Gravity::new(1.0, Solver::Direct), Box::new(integrator));
+        let (heavy, light) = (1.0 - 1e-6, 1e-6);
+        let periapsis = 1.0 - eccentricity;
+        let speed = ((1.0 + eccentricity) / periapsis).sqrt();
+        for (mass, pos, vel) in [
+            (heavy, -light * periapsis, -light * speed),
+            (light, heavy * periapsis, heavy * speed),
+        ] {
+            let mut gbody = GBody::new(String::new(), Srgba::WHITE, 0.0, mass);
+            gbody.set_motion(vec3(pos, 0.0, 0.0), vec3(0.0, vel, 0.0));
+            pmesh.add(gbody);
+        }
+        pmesh
+    }
+
+    /// Largest relative energy drift in each half of `orbits` orbits
+    /// taken `steps` ticks each
+    fn energy_drift(mut pmesh: PhysicsMesh, orbits: usize, steps: usize) -> (f64, f64) {
+        let initial = pmesh.total_energy();
+        let mut drift = [0.0_f64; 2];
+        for i in 0..orbits * steps {
+            pmesh.compute(TAU / steps as f64);
+            let half = &mut drift[2 * i / (orbits * steps)];
+            *half = half.max(((pmesh.total_energy() - initial) / initial).abs());
+        }
+        (drift[0], drift[1])
+    }
+
+    #[test]
+    fn symplectic_integrators_keep_energy_bounded() {
+        for (name, pmesh, bound) in [
+            ("verlet", kepler(VelocityVerlet::default(), 0.5), 1e-3),
+            ("leapfrog", kepler(LeapfrogKDK::default(), 0.5), 1e-3),
+            ("yoshida4", kepler(Yoshida4::default(), 0.5), 1e-6),
+        ] {
+            let (first, second) = energy_drift(pmesh, 40, 500);
+            assert!(first < bound, "{name} drifted {first}");
+            // oscillating, not growing
+            assert!(
+                second < first * 1.01,
+                "{name} went from {first} to {second}"
+            );
+        }
+    }
+
+    #[test]
+    fn rk45_stays_within_tolerance_through_a_close_encounter() {
+        // two orbits, the middle of which passes 1000 times closer than
+        // the far end, a hundred frames each
+        let run = |tolerance| {
+            let mut pmesh = kepler(DormandPrince::new(tolerance, 100_000), 0.999);
+            let (start, initial) = (pmesh.components[1].pos, pmesh.total_energy());
+            for _ in 0..200 {
+                pmesh.compute(TAU / 100.0);
+            }
+            let drift = ((pmesh.total_energy() - initial) / initial).abs();
+            (drift, pmesh.components[1].pos.distance(start))
+        };
+
+        let (loose_drift, loose_miss) = run(1e-8);
+        let (tight_drift, tight_miss) = run(1e-10);
+        assert!(loose_drift < 1e-5, "drifted {loose_drift}");
+        assert!(tight_drift < 1e-7, "drifted {tight_drift}");
+        assert!(tight_miss < 1e-4, "missed the start by {tight_miss}");
+        assert!(tight_miss < loose_miss / 10.0);
+    }
+}
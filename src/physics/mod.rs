@@ -1,149 +1,150 @@
-pub mod lighting;
-
+pub mod collision;
+pub mod diagnostics;
+pub mod gravity;
+pub mod integrator;
+pub mod octree;
+pub mod orbit;
+pub mod timestep;
+
+use collision::{CollisionMode, Collisions};
+use gravity::Gravity;
+use integrator::Integrator;
 use three_d::*;
 
+/// Plain physics state, drawing it is up to `render::BodyRenderer`
+#[derive(Clone)]
 pub struct GBody {
     pub name: String,
-    pub pos: Vector3<f32>,
-    pub vel: Vector3<f32>,
+    pub pos: Vector3<f64>,
+    /// Position before the last tick, to interpolate in between
+    pub prev_pos: Vector3<f64>,
+    pub vel: Vector3<f64>,
     pub color: Srgba,
     pub radius: f32,
-    pub mass: f32,
-    pub gm_sphere: Gm<Mesh, PhysicalMaterial>,
+    pub mass: f64,
+    /// Overrides the global softening length for pairs involving this body
+    pub softening: Option<f64>,
+    /// Overrides the global collision mode for this body
+    pub collision: Option<CollisionMode>,
 }
 
 impl GBody {
-    pub fn new(
-        ctx: &Context,
-        name: String,
-        color: Srgba,
-        radius: f32,
-        mass: f32,
-    ) -> Result<Self, three_d_asset::Error> {
-        let mut sphere_mesh = CpuMesh::sphere(16);
-        sphere_mesh.transform(&Mat4::from_scale(radius))?;
-        let gm_sphere = Gm::new(
-            Mesh::new(&ctx, &sphere_mesh),
-            PhysicalMaterial::new_opaque(
-                &ctx,
-                &CpuMaterial {
-                    albedo: color,
-                    ..Default::default()
-                },
-            ),
-        );
-        Ok(Self {
+    pub fn new(name: String, color: Srgba, radius: f32, mass: f64) -> Self {
+        Self {
             name,
             pos: Vector3::zero(),
+            prev_pos: Vector3::zero(),
             vel: Vector3::zero(),
             color,
             radius,
             mass,
-            gm_sphere,
-        })
+            softening: None,
+            collision: None,
+        }
     }
 
-    pub fn set_motion(&mut self, pos: Vector3<f32>, vel: Vector3<f32>) {
+    /// Moves the body without it having travelled there, so nothing
+    /// interpolates from where it was. Integrators change `pos` and `vel`
+    /// themselves instead.
+    pub fn set_motion(&mut self, pos: Vector3<f64>, vel: Vector3<f64>) {
         self.pos = pos;
+        self.prev_pos = pos;
         self.vel = vel;
     }
 
-    pub fn displace(&mut self, dr: Vector3<f32>) {
+    pub fn displace(&mut self, dr: Vector3<f64>) {
         self.pos += dr;
     }
 
-    pub fn accelerate(&mut self, dv: Vector3<f32>) {
+    pub fn accelerate(&mut self, dv: Vector3<f64>) {
         self.vel += dv;
     }
 
-    pub fn accelerate_to(&mut self, dv: f32, to: Vector3<f32>) {
+    pub fn accelerate_to(&mut self, dv: f64, to: Vector3<f64>) {
         let vec_u = (to - self.pos).normalize();
         self.accelerate(vec_u * dv);
     }
 
-    pub fn process(&mut self, dt: f32) {
+    pub fn process(&mut self, dt: f64) {
         self.displace(self.vel * dt);
     }
 
-    pub fn render(&mut self) {
-        self.gm_sphere
-            .set_transformation(Mat4::from_translation(self.pos));
+    /// Position `alpha` of the way from the previous tick's (0) to the
+    /// current one (1)
+    pub fn interpolated_pos(&self, alpha: f64) -> Vector3<f64> {
+        self.prev_pos.lerp(self.pos, alpha)
     }
 }
 
 pub struct PhysicsMesh {
-    pub const_g: f32,
+    pub gravity: Gravity,
+    pub integrator: Box<dyn Integrator>,
+    pub collisions: Collisions,
     pub components: Vec<GBody>,
+    /// Simulated time so far
+    pub time: f64,
 }
 
 impl Default for PhysicsMesh {
     fn default() -> Self {
+        Self::new(
+            Gravity::default(),
+            Box::new(integrator::VelocityVerlet::default()),
+        )
+    }
+}
+
+impl PhysicsMesh {
+    pub fn new(gravity: Gravity, integrator: Box<dyn Integrator>) -> Self {
         Self {
-            const_g: crate::consts::GRAVITATIONAL_CONSTANT,
+            gravity,
+            integrator,
+            collisions: Collisions::default(),
             components: vec![],
+            time: 0.0,
         }
     }
-}
-impl IntoIterator for PhysicsMesh {
-    type Item = Gm<Mesh, PhysicalMaterial>;
-    type IntoIter = std::vec::IntoIter<Self::Item>;
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.components
-            .into_iter()
-            .map(|gbody| gbody.gm_sphere)
-            .collect::<Vec<_>>()
-            .into_iter()
+    pub fn with_collisions(mut self, collisions: Collisions) -> Self {
+        self.collisions = collisions;
+        self
     }
-}
 
-impl PhysicsMesh {
     pub fn add(&mut self, component: GBody) {
         self.components.push(component);
     }
 
-    pub fn get_mesh(&mut self) -> Vec<&Mesh> {
+    pub fn compute(&mut self, dt: f64) {
         self.components
-            .iter()
-            .map(|e| &e.gm_sphere.geometry)
-            .collect()
+            .iter_mut()
+            .for_each(|gbody| gbody.prev_pos = gbody.pos);
+        self.integrator
+            .step(&mut self.components, &self.gravity, dt);
+        self.collisions.resolve(&mut self.components);
+        self.time += dt;
     }
+}
 
-    pub fn compute(&mut self, dt: f32) {
-        // Here we just change the speed, doesn't influence
-        // the other bodies on their computations
-        for i in 0..self.components.len() {
-            let from = &self.components[i];
-            let mut acc_accel = Vector3::zero();
-
-            for to in &self.components {
-                if from.pos == to.pos {
-                    continue;
-                }
-
-                let distance_sq = from.pos.distance2(to.pos);
-                let accel = (self.const_g * to.mass) / distance_sq;
-                acc_accel += accel * (to.pos - from.pos).normalize();
-            }
-
-            let from = &mut self.components[i];
-            from.accelerate(acc_accel * dt);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // And this would apply those speeds, once it has been computer
-        // without changing them "live"
-        self.components
-            .iter_mut()
-            .for_each(|gbody| gbody.process(dt));
-    }
+    #[test]
+    fn rk45_keeps_the_previous_position_to_interpolate_from() {
+        let mut mesh = PhysicsMesh::new(
+            Gravity::new(1.0, gravity::Solver::Direct),
+            Box::new(integrator::DormandPrince::new(1e-9, 1000)),
+        );
+        for (x, vy) in [(-1.0, -0.5), (1.0, 0.5)] {
+            let mut gbody = GBody::new(String::new(), Srgba::WHITE, 0.1, 1.0);
+            gbody.set_motion(vec3(x, 0.0, 0.0), vec3(0.0, vy, 0.0));
+            mesh.add(gbody);
+        }
 
-    pub fn render(&mut self) -> Vec<&dyn Object> {
-        self.components
-            .iter_mut()
-            .map(|gbody| {
-                gbody.render();
-                &gbody.gm_sphere as &dyn Object
-            })
-            .collect()
+        mesh.compute(0.1);
+        for (gbody, x) in mesh.components.iter().zip([-1.0, 1.0]) {
+            assert_eq!(gbody.interpolated_pos(0.0), vec3(x, 0.0, 0.0));
+            assert_ne!(gbody.interpolated_pos(1.0), gbody.prev_pos);
+        }
     }
 }
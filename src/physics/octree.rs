@@ -0,0 +1,259 @@
+use three_d::*;
+
+use super::gravity::pair_accel;
+
+/// Past this depth cells stop splitting and just pile up bodies, only
+/// (nearly) coincident bodies get this far. Cells are then a millionth of
+/// the whole tree, still well within float precision so `Node::contains`
+/// doesn't lie.
+const MAX_DEPTH: u32 = 20;
+const NONE: u32 = u32::MAX;
+
+struct Node {
+    center: Vector3<f64>,
+    half_size: f64,
+    mass: f64,
+    center_of_mass: Vector3<f64>,
+    /// Index of the first of 8 consecutive children, `NONE` on leaves
+    children: u32,
+    /// First body of the leaf, the rest follow through `Octree::next`
+    body: u32,
+}
+
+impl Node {
+    fn new(center: Vector3<f64>, half_size: f64) -> Self {
+        Self {
+            center,
+            half_size,
+            mass: 0.0,
+            center_of_mass: Vector3::zero(),
+            children: NONE,
+            body: NONE,
+        }
+    }
+
+    fn octant(&self, pos: Vector3<f64>) -> u32 {
+        (pos.x >= self.center.x) as u32
+            | ((pos.y >= self.center.y) as u32) << 1
+            | ((pos.z >= self.center.z) as u32) << 2
+    }
+
+    fn contains(&self, pos: Vector3<f64>) -> bool {
+        let d = pos - self.center;
+        d.x.abs() <= self.half_size && d.y.abs() <= self.half_size && d.z.abs() <= self.half_size
+    }
+}
+
+/// Barnes-Hut tree over a snapshot of body positions and masses
+pub struct Octree<'a> {
+    positions: &'a [Vector3<f64>],
+    masses: &'a [f64],
+    nodes: Vec<Node>,
+    /// Linked list of bodies sharing a leaf
+    next: Vec<u32>,
+}
+
+impl<'a> Octree<'a> {
+    pub fn new(positions: &'a [Vector3<f64>], masses: &'a [f64]) -> Self {
+        let mut tree = Self {
+            positions,
+            masses,
+            nodes: Vec::with_capacity(positions.len() * 2),
+            next: vec![NONE; positions.len()],
+        };
+        if positions.is_empty() {
+            return tree;
+        }
+
+        let (min, max) = positions
+            .iter()
+            .fold((positions[0], positions[0]), |(min, max), p| {
+                (
+                    vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                    vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                )
+            });
+        let extent = max - min;
+        let half_size = extent.x.max(extent.y).max(extent.z) / 2.0 * 1.001 + f64::EPSILON;
+        tree.nodes.push(Node::new((min + max) / 2.0, half_size));
+
+        for i in 0..positions.len() {
+            tree.insert(i as u32);
+        }
+        tree.summarize();
+        tree
+    }
+
+    fn insert(&mut self, body: u32) {
+        let pos = self.positions[body as usize];
+        let mut node = 0;
+        let mut depth = 0;
+        loop {
+            let current = &self.nodes[node];
+            if current.children != NONE {
+                node = (current.children + current.octant(pos)) as usize;
+                depth += 1;
+            } else if current.body == NONE {
+                self.nodes[node].body = body;
+                return;
+            } else if depth >= MAX_DEPTH {
+                self.next[body as usize] = current.body;
+                self.nodes[node].body = body;
+                return;
+            } else {
+                self.split(node);
+            }
+        }
+    }
+
+    /// Turns a leaf with one body into a parent of 8 leaves
+    fn split(&mut self, node: usize) {
+        let (center, half_size, body) = {
+            let n = &self.nodes[node];
+            (n.center, n.half_size / 2.0, n.body)
+        };
+        let children = self.nodes.len() as u32;
+        for octant in 0..8 {
+            let sign = |bit: u32| {
+                if octant & bit != 0 {
+                    half_size
+                } else {
+                    -half_size
+                }
+            };
+            self.nodes.push(Node::new(
+                center + vec3(sign(1), sign(2), sign(4)),
+                half_size,
+            ));
+        }
+
+        let n = &mut self.nodes[node];
+        n.children = children;
+        n.body = NONE;
+        let child = (children + n.octant(self.positions[body as usize])) as usize;
+        self.nodes[child].body = body;
+    }
+
+    /// Fills every node's mass and center of mass, children always come
+    /// after their parents so going backwards visits them first
+    fn summarize(&mut self) {
+        for node in (0..self.nodes.len()).rev() {
+            let (mut mass, mut weighted) = (0.0, Vector3::zero());
+            let n = &self.nodes[node];
+            if n.children != NONE {
+                for child in &self.nodes[n.children as usize..n.children as usize + 8] {
+                    mass += child.mass;
+                    weighted += child.center_of_mass * child.mass;
+                }
+            } else {
+                let mut body = n.body;
+                while body != NONE {
+                    mass += self.masses[body as usize];
+                    weighted += self.positions[body as usize] * self.masses[body as usize];
+                    body = self.next[body as usize];
+                }
+            }
+
+            let n = &mut self.nodes[node];
+            n.mass = mass;
+            if mass != 0.0 {
+                n.center_of_mass = weighted / mass;
+            }
+        }
+    }
+
+    /// Acceleration felt by body `body` from all the others. Bodies close
+    /// enough to be visited one by one use the same pairwise softening as
+    /// direct sum, lumped cells just use `body`'s own.
+    pub fn acceleration(
+        &self,
+        body: usize,
+        theta: f64,
+        const_g: f64,
+        softenings_sq: &[f64],
+    ) -> Vector3<f64> {
+        let mut acc_accel = Vector3::zero();
+        if self.nodes.is_empty() {
+            return acc_accel;
+        }
+
+        let from = self.positions[body];
+        let theta_sq = theta * theta;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let n = &self.nodes[node];
+            if n.mass == 0.0 {
+                continue;
+            }
+
+            if n.children == NONE {
+                let mut to = n.body;
+                while to != NONE {
+                    if to as usize != body {
+                        acc_accel += pair_accel(
+                            const_g,
+                            from,
+                            self.positions[to as usize],
+                            self.masses[to as usize],
+                            (softenings_sq[body] + softenings_sq[to as usize]) / 2.0,
+                        );
+                    }
+                    to = self.next[to as usize];
+                }
+                continue;
+            }
+
+            let size = n.half_size * 2.0;
+            let distance_sq = from.distance2(n.center_of_mass);
+            if !n.contains(from) && size * size < theta_sq * distance_sq {
+                acc_accel +=
+                    pair_accel(const_g, from, n.center_of_mass, n.mass, softenings_sq[body]);
+            } else {
+                stack.extend(n.children as usize..n.children as usize + 8);
+            }
+        }
+        acc_accel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use three_d::*;
+
+    use crate::physics::{
+        gravity::{Gravity, Solver},
+        GBody,
+    };
+
+    #[test]
+    fn opening_angle_0_matches_direct_sum() {
+        // spread out evenly but irregularly, a few sharing a spot and a
+        // few with their own softening
+        let bodies: Vec<_> = (0..500)
+            .map(|i| {
+                let i = (i % 490) as f64;
+                let frac = |a: f64| (0.5 + a * i).fract() - 0.5;
+                let mut gbody = GBody::new(String::new(), Srgba::WHITE, 0.0, 1.0 + frac(0.31));
+                gbody.pos = vec3(frac(0.8191725), frac(0.6710436), frac(0.5497005)) * 100.0;
+                gbody.softening = (i % 7.0 == 0.0).then_some(0.5);
+                gbody
+            })
+            .collect();
+
+        let accelerations = |solver| {
+            let mut out = vec![];
+            Gravity::new(1.0, solver)
+                .with_softening(0.1)
+                .accelerations(&bodies, &mut out);
+            out
+        };
+        let direct = accelerations(Solver::Direct);
+        let tree = accelerations(Solver::BarnesHut { theta: 0.0 });
+        for (a, b) in direct.iter().zip(&tree) {
+            assert!(
+                (a - b).magnitude() <= 1e-12 * a.magnitude(),
+                "{a:?} != {b:?}"
+            );
+        }
+    }
+}
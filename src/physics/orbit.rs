@@ -0,0 +1,309 @@
+use three_d::*;
+
+use super::PhysicsMesh;
+
+/// Shape, orientation and position along a Keplerian orbit around some
+/// parent, angles in radians
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitalElements {
+    /// Negative for hyperbolic orbits
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub longitude_of_ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    pub true_anomaly: f64,
+}
+
+impl OrbitalElements {
+    /// Below this eccentricity or inclination (radians) the orbit is taken
+    /// as circular or equatorial, where some angles stop being defined
+    const EPSILON: f64 = 1e-9;
+
+    /// Position and velocity relative to the parent, `mu` being the
+    /// standard gravitational parameter G·(M + m). The reference plane is
+    /// xy with z up, same as the default camera.
+    pub fn to_state_vectors(&self, mu: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let e = self.eccentricity;
+        let nu = self.true_anomaly;
+        // semi-latus rectum
+        let p = self.semi_major_axis * (1.0 - e * e);
+
+        let r = p / (1.0 + e * nu.cos());
+        let pos = vec3(r * nu.cos(), r * nu.sin(), 0.0);
+        let vel = vec3(-nu.sin(), e + nu.cos(), 0.0) * (mu / p).sqrt();
+
+        let rotation = Matrix3::from_angle_z(Rad(self.longitude_of_ascending_node))
+            * Matrix3::from_angle_x(Rad(self.inclination))
+            * Matrix3::from_angle_z(Rad(self.argument_of_periapsis));
+        (rotation * pos, rotation * vel)
+    }
+
+    /// Osculating elements of a body at `pos` with `vel` relative to its
+    /// parent. Angles that are undefined for circular or equatorial orbits
+    /// are set to 0 and the position is carried by the true anomaly.
+    pub fn from_state_vectors(pos: Vector3<f64>, vel: Vector3<f64>, mu: f64) -> Self {
+        let r = pos.magnitude();
+        let h = pos.cross(vel);
+        let node = vec3(-h.y, h.x, 0.0);
+        let e_vec = (pos * (vel.magnitude2() - mu / r) - vel * pos.dot(vel)) / mu;
+        let e = e_vec.magnitude();
+        let energy = vel.magnitude2() / 2.0 - mu / r;
+
+        let angle = |a: Vector3<f64>, b: Vector3<f64>| {
+            (a.dot(b) / (a.magnitude() * b.magnitude()))
+                .clamp(-1.0, 1.0)
+                .acos()
+        };
+        // acos only gives half the circle, `past_half` picks the other one
+        let full_angle = |angle: f64, past_half: bool| {
+            if past_half {
+                std::f64::consts::TAU - angle
+            } else {
+                angle
+            }
+        };
+
+        let inclination = (h.z / h.magnitude()).clamp(-1.0, 1.0).acos();
+        let equatorial = node.magnitude() < Self::EPSILON * h.magnitude();
+        let circular = e < Self::EPSILON;
+
+        let longitude_of_ascending_node = if equatorial {
+            0.0
+        } else {
+            node.y.atan2(node.x).rem_euclid(std::f64::consts::TAU)
+        };
+        // on equatorial orbits angles are measured from the x axis instead
+        // of the ascending node, retrograde ones going the other way
+        let reference = if equatorial { Vector3::unit_x() } else { node };
+        let retrograde = equatorial && h.z < 0.0;
+        let (argument_of_periapsis, true_anomaly) = if circular {
+            let past_half = if equatorial {
+                (pos.y < 0.0) != retrograde
+            } else {
+                pos.z < 0.0
+            };
+            (0.0, full_angle(angle(reference, pos), past_half))
+        } else {
+            let past_half = if equatorial {
+                (e_vec.y < 0.0) != retrograde
+            } else {
+                e_vec.z < 0.0
+            };
+            (
+                full_angle(angle(reference, e_vec), past_half),
+                full_angle(angle(e_vec, pos), pos.dot(vel) < 0.0),
+            )
+        };
+
+        Self {
+            semi_major_axis: -mu / (2.0 * energy),
+            eccentricity: e,
+            inclination,
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            true_anomaly,
+        }
+    }
+
+    /// Only closed orbits have one
+    pub fn period(&self, mu: f64) -> Option<f64> {
+        (self.eccentricity < 1.0)
+            .then(|| std::f64::consts::TAU * (self.semi_major_axis.powi(3) / mu).sqrt())
+    }
+
+    /// Closest distance to the parent
+    pub fn periapsis(&self) -> f64 {
+        self.semi_major_axis * (1.0 - self.eccentricity)
+    }
+
+    /// Farthest distance to the parent, open orbits go on forever
+    pub fn apoapsis(&self) -> Option<f64> {
+        (self.eccentricity < 1.0).then_some(self.semi_major_axis * (1.0 + self.eccentricity))
+    }
+}
+
+/// Orbit of a body around `reference` at some instant, if it stopped
+/// feeling anything else from then on
+#[derive(Debug, Clone, Copy)]
+pub struct Osculating {
+    /// Index of the body being orbited
+    pub reference: usize,
+    pub elements: OrbitalElements,
+    pub period: Option<f64>,
+    pub periapsis: f64,
+    pub apoapsis: Option<f64>,
+}
+
+impl PhysicsMesh {
+    /// The more massive body pulling the hardest on `body`, what it
+    /// orbits in the intuitive sense. `None` if nothing is heavier.
+    pub fn dominant_attractor(&self, body: usize) -> Option<usize> {
+        let from = &self.components[body];
+        self.components
+            .iter()
+            .enumerate()
+            .filter(|(i, to)| *i != body && to.mass > from.mass && to.pos != from.pos)
+            .map(|(i, to)| (i, to.mass / from.pos.distance2(to.pos)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    /// Osculating orbit of each body around `reference`, or around their
+    /// own dominant attractor if `None`. Bodies with nothing to orbit,
+    /// including the reference itself, get `None`.
+    pub fn osculating_elements(&self, reference: Option<usize>) -> Vec<Option<Osculating>> {
+        (0..self.components.len())
+            .map(|i| {
+                let reference = match reference {
+                    Some(reference) if reference != i => reference,
+                    Some(_) => return None,
+                    None => self.dominant_attractor(i)?,
+                };
+                let (body, parent) = (&self.components[i], &self.components[reference]);
+                if body.pos == parent.pos {
+                    return None;
+                }
+
+                let mu = self.gravity.const_g * (body.mass + parent.mass);
+                let elements = OrbitalElements::from_state_vectors(
+                    body.pos - parent.pos,
+                    body.vel - parent.vel,
+                    mu,
+                );
+                Some(Osculating {
+                    reference,
+                    elements,
+                    period: elements.period(mu),
+                    periapsis: elements.periapsis(),
+                    apoapsis: elements.apoapsis(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Solves Kepler's equation for the true anomaly given the mean one,
+/// works for both elliptic and hyperbolic orbits
+pub fn true_from_mean_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 64;
+    let e = eccentricity;
+
+    if e < 1.0 {
+        // M = E - e·sin(E), eccentric anomaly by Newton's method
+        let m = mean_anomaly.rem_euclid(std::f64::consts::TAU);
+        let mut ea = if e > 0.8 { std::f64::consts::PI } else { m };
+        for _ in 0..MAX_ITERATIONS {
+            let delta = (ea - e * ea.sin() - m) / (1.0 - e * ea.cos());
+            ea -= delta;
+            if delta.abs() < 1e-15 {
+                break;
+            }
+        }
+        2.0 * ((1.0 + e).sqrt() * (ea / 2.0).sin()).atan2((1.0 - e).sqrt() * (ea / 2.0).cos())
+    } else {
+        // M = e·sinh(H) - H, hyperbolic anomaly
+        let m = mean_anomaly;
+        let mut ha = (2.0 * m / e).asinh();
+        for _ in 0..MAX_ITERATIONS {
+            let delta = (e * ha.sinh() - ha - m) / (e * ha.cosh() - 1.0);
+            ha -= delta;
+            if delta.abs() < 1e-15 {
+                break;
+            }
+        }
+        2.0 * (((e + 1.0) / (e - 1.0)).sqrt() * (ha / 2.0).tanh()).atan()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{PI, TAU};
+
+    use super::*;
+
+    const MU: f64 = 3.986e14;
+
+    fn elements(
+        semi_major_axis: f64,
+        eccentricity: f64,
+        inclination: f64,
+        [longitude_of_ascending_node, argument_of_periapsis, true_anomaly]: [f64; 3],
+    ) -> OrbitalElements {
+        OrbitalElements {
+            semi_major_axis,
+            eccentricity,
+            inclination,
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            true_anomaly,
+        }
+    }
+
+    fn close(a: Vector3<f64>, b: Vector3<f64>) -> bool {
+        (a - b).magnitude() <= 1e-9 * a.magnitude().max(b.magnitude())
+    }
+
+    fn same_angle(a: f64, b: f64) -> bool {
+        let d = (a - b).rem_euclid(TAU);
+        d.min(TAU - d) < 1e-9
+    }
+
+    /// Back to the same state vectors, whatever elements it went through
+    fn round_trips(elements: OrbitalElements) -> OrbitalElements {
+        let (pos, vel) = elements.to_state_vectors(MU);
+        let back = OrbitalElements::from_state_vectors(pos, vel, MU);
+        let (back_pos, back_vel) = back.to_state_vectors(MU);
+        assert!(close(pos, back_pos), "{elements:?} came back as {back:?}");
+        assert!(close(vel, back_vel), "{elements:?} came back as {back:?}");
+        back
+    }
+
+    #[test]
+    fn round_trips_inclined_orbits() {
+        for original in [
+            elements(7e6, 0.3, 0.9, [1.2, 4.0, 2.5]),
+            elements(4.2e7, 0.7, 2.5, [5.9, 0.2, 4.0]),
+            // hyperbolic, still inside the asymptotes
+            elements(-2e7, 1.5, 0.4, [0.7, 3.3, -1.5]),
+        ] {
+            let back = round_trips(original);
+            assert!((back.semi_major_axis / original.semi_major_axis - 1.0).abs() < 1e-9);
+            assert!((back.eccentricity - original.eccentricity).abs() < 1e-9);
+            assert!((back.inclination - original.inclination).abs() < 1e-9);
+            for (a, b) in [
+                (
+                    back.longitude_of_ascending_node,
+                    original.longitude_of_ascending_node,
+                ),
+                (back.argument_of_periapsis, original.argument_of_periapsis),
+                (back.true_anomaly, original.true_anomaly),
+            ] {
+                assert!(same_angle(a, b), "{original:?} came back as {back:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_equatorial_and_circular_orbits() {
+        for original in [
+            // equatorial, the node is undefined
+            elements(7e6, 0.3, 0.0, [1.2, 4.0, 2.5]),
+            // retrograde equatorial
+            elements(7e6, 0.3, PI, [0.0, 1.0, 5.0]),
+            // circular, the periapsis is undefined
+            elements(7e6, 0.0, 0.9, [1.2, 0.0, 4.5]),
+            // both
+            elements(7e6, 0.0, 0.0, [0.0, 0.0, 3.5]),
+            elements(-2e7, 1.5, 0.0, [0.0, 2.0, 1.0]),
+        ] {
+            let back = round_trips(original);
+            if original.inclination == 0.0 || original.inclination == PI {
+                assert_eq!(back.longitude_of_ascending_node, 0.0);
+            }
+            if original.eccentricity == 0.0 {
+                assert_eq!(back.argument_of_periapsis, 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use super::PhysicsMesh;
+
+/// Turns whatever time a frame took into a whole number of fixed size
+/// physics ticks, so trajectories don't depend on the framerate
+pub struct FixedTimestep {
+    /// Tick size in simulated time, `0` makes every frame a single tick of
+    /// whatever size it was (framerate dependent)
+    pub dt: f64,
+    /// Ticks allowed per frame, past it the simulation just slows down
+    pub max_steps: u32,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    pub fn new(dt: f64, max_steps: u32) -> Self {
+        Self {
+            dt,
+            max_steps,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Feeds `elapsed` simulated time and runs as many ticks as fit in the
+    /// accumulated time. Returns how far we are into the next tick, from
+    /// 0 to 1, to interpolate rendered positions with.
+    pub fn advance(&mut self, pmesh: &mut PhysicsMesh, elapsed: f64) -> f32 {
+        self.advance_with(pmesh, elapsed, |_, _| {})
+    }
+
+    /// `advance`, calling `on_tick` with the state after every tick taken
+    /// and its size
+    pub fn advance_with(
+        &mut self,
+        pmesh: &mut PhysicsMesh,
+        elapsed: f64,
+        mut on_tick: impl FnMut(&PhysicsMesh, f64),
+    ) -> f32 {
+        if self.dt <= 0.0 {
+            pmesh.compute(elapsed);
+            on_tick(pmesh, elapsed);
+            return 1.0;
+        }
+
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.dt {
+            if steps >= self.max_steps {
+                println!(
+                    "physics falling behind, dropping {} ticks",
+                    (self.accumulator / self.dt).floor()
+                );
+                self.accumulator %= self.dt;
+                break;
+            }
+            pmesh.compute(self.dt);
+            on_tick(pmesh, self.dt);
+            self.accumulator -= self.dt;
+            steps += 1;
+        }
+
+        (self.accumulator / self.dt) as f32
+    }
+}
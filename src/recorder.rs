@@ -0,0 +1,216 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::physics::PhysicsMesh;
+
+/// Magic bytes starting a binary trajectory, followed by a `u32` version
+/// and a `u8` that's 1 if samples carry energy
+const BINARY_MAGIC: &[u8; 4] = b"GBTR";
+const BINARY_VERSION: u32 = 1;
+/// Binary record tags. A body table is a `u32` count and as many names
+/// (`u32` byte length, utf-8) and comes before the first sample and
+/// again whenever bodies change. A sample is its time, the total energy
+/// if enabled, then position and velocity of each body in the table, all
+/// `f64`. Everything is little endian.
+const BINARY_BODIES: u8 = 0;
+const BINARY_SAMPLE: u8 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// A row per body per sample
+    Csv,
+    /// A line per sample, bodies in an array
+    #[serde(alias = "jsonl")]
+    JsonLines,
+    /// See `BINARY_MAGIC` and `BINARY_BODIES`
+    Binary,
+}
+
+impl OutputFormat {
+    /// Format going with `path`'s extension, if any does
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(Self::Csv),
+            "jsonl" | "ndjson" => Some(Self::JsonLines),
+            "bin" | "traj" => Some(Self::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// Streams every body's state out every `interval` of simulated time,
+/// what's buffered only hits the file on `flush` or when it's dropped
+pub struct Recorder {
+    out: Box<dyn Write>,
+    pub format: OutputFormat,
+    /// `0` samples on every update
+    pub interval: f64,
+    pub energy: bool,
+    /// `None` until the first sample, which sets where the rest go
+    next_sample: Option<f64>,
+    /// Names the last binary body table was written with
+    bodies: Option<Vec<String>>,
+}
+
+impl Recorder {
+    pub fn new(out: Box<dyn Write>, format: OutputFormat, interval: f64, energy: bool) -> Self {
+        Self {
+            out,
+            format,
+            interval,
+            energy,
+            next_sample: None,
+            bodies: None,
+        }
+    }
+
+    pub fn create(
+        path: &Path,
+        format: OutputFormat,
+        interval: f64,
+        energy: bool,
+    ) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .map_err(|err| anyhow::anyhow!("can't record to {}: {err}", path.display()))?;
+        let mut recorder = Self::new(Box::new(BufWriter::new(file)), format, interval, energy);
+        recorder.write_header()?;
+        Ok(recorder)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        match self.format {
+            OutputFormat::Csv => {
+                write!(self.out, "time,body,x,y,z,vx,vy,vz")?;
+                if self.energy {
+                    write!(self.out, ",energy")?;
+                }
+                writeln!(self.out)
+            }
+            OutputFormat::JsonLines => Ok(()),
+            OutputFormat::Binary => {
+                self.out.write_all(BINARY_MAGIC)?;
+                self.out.write_all(&BINARY_VERSION.to_le_bytes())?;
+                self.out.write_all(&[self.energy as u8])
+            }
+        }
+    }
+
+    /// Samples `pmesh` if it got to the next multiple of `interval` since
+    /// the first sample
+    pub fn update(&mut self, pmesh: &PhysicsMesh) -> anyhow::Result<()> {
+        let mut next_sample = match self.next_sample {
+            Some(next_sample) if pmesh.time < next_sample => return Ok(()),
+            Some(next_sample) => next_sample,
+            None => pmesh.time,
+        };
+        // on the same grid however the ticks fall, skipping the samples
+        // a long tick went past
+        if self.interval > 0.0 {
+            while next_sample <= pmesh.time {
+                next_sample += self.interval;
+            }
+        }
+        self.next_sample = Some(next_sample);
+
+        let energy = self.energy.then(|| pmesh.total_energy());
+        match self.format {
+            OutputFormat::Csv => self.write_csv(pmesh, energy)?,
+            OutputFormat::JsonLines => self.write_json(pmesh, energy)?,
+            OutputFormat::Binary => self.write_binary(pmesh, energy)?,
+        }
+        Ok(())
+    }
+
+    /// Writes out what's buffered, once a frame as the window's loop never
+    /// returns to drop it
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn write_csv(&mut self, pmesh: &PhysicsMesh, energy: Option<f64>) -> std::io::Result<()> {
+        for gbody in &pmesh.components {
+            let name = if gbody.name.contains([',', '"', '\n']) {
+                format!("\"{}\"", gbody.name.replace('"', "\"\""))
+            } else {
+                gbody.name.clone()
+            };
+            let (pos, vel) = (gbody.pos, gbody.vel);
+            write!(
+                self.out,
+                "{},{name},{},{},{},{},{},{}",
+                pmesh.time, pos.x, pos.y, pos.z, vel.x, vel.y, vel.z
+            )?;
+            if let Some(energy) = energy {
+                write!(self.out, ",{energy}")?;
+            }
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    fn write_json(&mut self, pmesh: &PhysicsMesh, energy: Option<f64>) -> std::io::Result<()> {
+        let bodies: Vec<_> = pmesh
+            .components
+            .iter()
+            .map(|gbody| {
+                json!({
+                    "name": gbody.name,
+                    "pos": [gbody.pos.x, gbody.pos.y, gbody.pos.z],
+                    "vel": [gbody.vel.x, gbody.vel.y, gbody.vel.z],
+                })
+            })
+            .collect();
+        let mut sample = json!({ "time": pmesh.time, "bodies": bodies });
+        if let Some(energy) = energy {
+            sample["energy"] = json!(energy);
+        }
+        serde_json::to_writer(&mut self.out, &sample)?;
+        writeln!(self.out)
+    }
+
+    fn write_binary(&mut self, pmesh: &PhysicsMesh, energy: Option<f64>) -> std::io::Result<()> {
+        let names = pmesh.components.iter().map(|gbody| &gbody.name);
+        if !self
+            .bodies
+            .as_ref()
+            .is_some_and(|bodies| names.clone().eq(bodies))
+        {
+            self.out.write_all(&[BINARY_BODIES])?;
+            self.out
+                .write_all(&(pmesh.components.len() as u32).to_le_bytes())?;
+            for name in names.clone() {
+                self.out.write_all(&(name.len() as u32).to_le_bytes())?;
+                self.out.write_all(name.as_bytes())?;
+            }
+            self.bodies = Some(names.cloned().collect());
+        }
+
+        self.out.write_all(&[BINARY_SAMPLE])?;
+        self.out.write_all(&pmesh.time.to_le_bytes())?;
+        if let Some(energy) = energy {
+            self.out.write_all(&energy.to_le_bytes())?;
+        }
+        for gbody in &pmesh.components {
+            for c in [gbody.pos, gbody.vel].iter().flat_map(|v| [v.x, v.y, v.z]) {
+                self.out.write_all(&c.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.out.flush() {
+            println!("couldn't finish recording: {err}");
+        }
+    }
+}
@@ -0,0 +1,62 @@
+pub mod lighting;
+
+use three_d::*;
+
+use crate::physics::PhysicsMesh;
+
+/// GPU side of the bodies in a `PhysicsMesh`, sphere `i` draws body `i`
+pub struct BodyRenderer {
+    ctx: Context,
+    spheres: Vec<Gm<Mesh, PhysicalMaterial>>,
+}
+
+impl BodyRenderer {
+    pub fn new(ctx: &Context) -> Self {
+        Self {
+            ctx: ctx.clone(),
+            spheres: vec![],
+        }
+    }
+
+    /// Moves the spheres to the bodies, `alpha` of the way from the previous
+    /// tick to the current one. This is the only place the f64 physics state
+    /// gets down to f32
+    pub fn update(&mut self, pmesh: &PhysicsMesh, alpha: f32) {
+        // spheres all look the same until placed here, so when merges take
+        // bodies out it's enough to drop the extra ones off the end
+        self.spheres.truncate(pmesh.components.len());
+        while self.spheres.len() < pmesh.components.len() {
+            self.spheres.push(sphere(&self.ctx));
+        }
+
+        for (gm_sphere, gbody) in self.spheres.iter_mut().zip(&pmesh.components) {
+            let pos = gbody.interpolated_pos(alpha as f64).map(|c| c as f32);
+            gm_sphere.set_transformation(
+                Mat4::from_translation(pos) * Mat4::from_scale(gbody.radius),
+            );
+            gm_sphere.material.albedo = gbody.color;
+        }
+    }
+
+    pub fn meshes(&self) -> Vec<&Mesh> {
+        self.spheres
+            .iter()
+            .map(|gm_sphere| &gm_sphere.geometry)
+            .collect()
+    }
+
+    pub fn objects(&self) -> Vec<&dyn Object> {
+        self.spheres
+            .iter()
+            .map(|gm_sphere| gm_sphere as &dyn Object)
+            .collect()
+    }
+}
+
+/// Unit sphere, scaled by the body's `radius` on update as merges can change it
+fn sphere(ctx: &Context) -> Gm<Mesh, PhysicalMaterial> {
+    Gm::new(
+        Mesh::new(ctx, &CpuMesh::sphere(16)),
+        PhysicalMaterial::new_opaque(ctx, &CpuMaterial::default()),
+    )
+}
@@ -0,0 +1,495 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context as _};
+use serde::{Deserialize, Serialize};
+use three_d::{Event, Key, Vector3};
+
+use crate::{
+    config::Config,
+    physics::{integrator::Integrator, GBody, PhysicsMesh},
+};
+
+const PAUSE_KEY: Key = Key::Space;
+const REVERSE_KEY: Key = Key::R;
+/// Step a single tick back or forward, pausing
+const STEP_BACK_KEY: Key = Key::ArrowLeft;
+const STEP_FORWARD_KEY: Key = Key::ArrowRight;
+
+/// Ticks between the states kept around to seek back to
+const KEYFRAME_INTERVAL: usize = 512;
+
+/// Changes to the bodies from outside the simulation, like the debug
+/// overlay's buttons, replays apply them between the same ticks they
+/// happened at
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum Intervention {
+    SetMotion {
+        body: String,
+        pos: [f64; 3],
+        vel: [f64; 3],
+    },
+    Remove {
+        body: String,
+    },
+}
+
+impl PhysicsMesh {
+    pub fn intervene(&mut self, intervention: &Intervention) -> anyhow::Result<()> {
+        let find = |components: &[GBody], name: &str| {
+            components
+                .iter()
+                .position(|gbody| gbody.name == name)
+                .ok_or_else(|| anyhow!("no body named {name:?} to intervene on"))
+        };
+        match intervention {
+            Intervention::SetMotion { body, pos, vel } => {
+                let i = find(&self.components, body)?;
+                self.components[i].set_motion(Vector3::from(*pos), Vector3::from(*vel));
+            }
+            Intervention::Remove { body } => {
+                let i = find(&self.components, body)?;
+                self.components.remove(i);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplayEvent {
+    /// `count` ticks in a row, each `dt` long
+    Ticks {
+        dt: f64,
+        count: u64,
+    },
+    Intervene(Intervention),
+}
+
+/// A run, as the config it started from and everything that happened
+/// since. Stored as JSON lines, the config and then one event per line.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub config: Config,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("can't open replay {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let config = lines.next().ok_or_else(|| anyhow!("empty replay"))??;
+        let config = serde_json::from_str(&config).context("replay line 1")?;
+        let events = lines
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(&line?).with_context(|| format!("replay line {}", i + 2))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { config, events })
+    }
+}
+
+/// Writes down a live run as it goes, what's buffered only hits the file
+/// on `flush`
+pub struct ReplayWriter {
+    out: Box<dyn Write>,
+    buffered: Vec<ReplayEvent>,
+}
+
+impl ReplayWriter {
+    /// `config` must be exactly what the run was started from, minus
+    /// `output` as playing back shouldn't record over anything
+    pub fn create(path: &Path, config: &Config) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("can't record replay to {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let config = Config {
+            output: Default::default(),
+            ..config.clone()
+        };
+        serde_json::to_writer(&mut out, &config)?;
+        writeln!(out)?;
+        Ok(Self {
+            out: Box::new(out),
+            buffered: vec![],
+        })
+    }
+
+    pub fn tick(&mut self, dt: f64) {
+        match self.buffered.last_mut() {
+            Some(ReplayEvent::Ticks { dt: last_dt, count })
+                if last_dt.to_bits() == dt.to_bits() =>
+            {
+                *count += 1
+            }
+            _ => self.buffered.push(ReplayEvent::Ticks { dt, count: 1 }),
+        }
+    }
+
+    /// Applies `intervention` to `pmesh` and records it
+    pub fn intervene(
+        &mut self,
+        pmesh: &mut PhysicsMesh,
+        intervention: Intervention,
+    ) -> anyhow::Result<()> {
+        pmesh.intervene(&intervention)?;
+        self.buffered.push(ReplayEvent::Intervene(intervention));
+        Ok(())
+    }
+
+    /// Writes out everything so far, once a frame as the window's loop
+    /// never returns to do it at the end
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        for event in self.buffered.drain(..) {
+            serde_json::to_writer(&mut self.out, &event)?;
+            writeln!(self.out)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Everything a `PhysicsMesh` changes while stepping
+struct Keyframe {
+    time: f64,
+    components: Vec<GBody>,
+    integrator: Box<dyn Integrator>,
+}
+
+impl Keyframe {
+    fn take(pmesh: &PhysicsMesh) -> Self {
+        Self {
+            time: pmesh.time,
+            components: pmesh.components.clone(),
+            integrator: pmesh.integrator.clone(),
+        }
+    }
+
+    fn restore(&self, pmesh: &mut PhysicsMesh) {
+        pmesh.time = self.time;
+        pmesh.components.clone_from(&self.components);
+        pmesh.integrator = self.integrator.clone();
+    }
+}
+
+/// Plays a replay's events back on the `PhysicsMesh` built from its
+/// config, following the clock, and can pause, seek and go backwards.
+/// Going back restores the last keyframe before and redoes the ticks
+/// from there, so it's the same states the first time around.
+///
+/// Every tick and intervention is a step, but runs of ticks are kept as
+/// the one event they were recorded as.
+pub struct ReplayPlayer {
+    events: Vec<ReplayEvent>,
+    /// Steps up to the end of each event
+    ends: Vec<usize>,
+    /// Simulated time at the end of each event
+    end_times: Vec<f64>,
+    /// Steps applied so far
+    cursor: usize,
+    /// State at every `KEYFRAME_INTERVAL` steps reached so far
+    keyframes: Vec<Keyframe>,
+    /// Simulated time playback is at, the bodies are at the last step before it
+    clock: f64,
+    pub paused: bool,
+    pub reversed: bool,
+}
+
+impl ReplayPlayer {
+    /// `pmesh` must be freshly built from the replay's config
+    pub fn new(events: Vec<ReplayEvent>, pmesh: &PhysicsMesh) -> Self {
+        let mut ends = Vec::with_capacity(events.len());
+        let mut end_times = Vec::with_capacity(events.len());
+        let (mut steps, mut time) = (0, pmesh.time);
+        for event in &events {
+            match event {
+                ReplayEvent::Ticks { dt, count } => {
+                    steps += *count as usize;
+                    // same additions in the same order as `PhysicsMesh::compute` does
+                    for _ in 0..*count {
+                        time += dt;
+                    }
+                }
+                ReplayEvent::Intervene(_) => steps += 1,
+            }
+            ends.push(steps);
+            end_times.push(time);
+        }
+
+        Self {
+            events,
+            ends,
+            end_times,
+            cursor: 0,
+            keyframes: vec![Keyframe::take(pmesh)],
+            clock: pmesh.time,
+            paused: false,
+            reversed: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.last().copied().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn handle_events(
+        &mut self,
+        events: &mut [Event],
+        pmesh: &mut PhysicsMesh,
+    ) -> anyhow::Result<()> {
+        for event in events {
+            let Event::KeyPress {
+                kind,
+                handled: handled @ false,
+                ..
+            } = event
+            else {
+                continue;
+            };
+            match *kind {
+                PAUSE_KEY => self.paused = !self.paused,
+                REVERSE_KEY => self.reversed = !self.reversed,
+                STEP_BACK_KEY => {
+                    self.paused = true;
+                    self.seek(pmesh, self.cursor.saturating_sub(1))?;
+                }
+                STEP_FORWARD_KEY => {
+                    self.paused = true;
+                    self.seek(pmesh, self.cursor + 1)?;
+                }
+                _ => continue,
+            }
+            *handled = true;
+        }
+        Ok(())
+    }
+
+    /// Moves the clock `elapsed` simulated time, backwards if `reversed`,
+    /// and the bodies along with it. Pauses at either end.
+    pub fn advance(&mut self, pmesh: &mut PhysicsMesh, elapsed: f64) -> anyhow::Result<()> {
+        if self.paused {
+            return Ok(());
+        }
+        let (start, end) = self.time_range();
+        let clock = if self.reversed {
+            self.clock - elapsed
+        } else {
+            self.clock + elapsed
+        };
+        if clock <= start || clock >= end {
+            self.paused = true;
+        }
+        self.seek_time(pmesh, clock)
+    }
+
+    /// Simulated time the replay starts and ends at
+    pub fn time_range(&self) -> (f64, f64) {
+        let start = self.keyframes[0].time;
+        (start, self.end_times.last().copied().unwrap_or(start))
+    }
+
+    /// Steps before event `i`, and the simulated time it starts at
+    fn event_start(&self, i: usize) -> (usize, f64) {
+        match i.checked_sub(1) {
+            Some(before) => (self.ends[before], self.end_times[before]),
+            None => (0, self.keyframes[0].time),
+        }
+    }
+
+    /// Puts the bodies where they were at `time`
+    pub fn seek_time(&mut self, pmesh: &mut PhysicsMesh, time: f64) -> anyhow::Result<()> {
+        let (start, end) = self.time_range();
+        self.clock = time.clamp(start, end);
+        // the first event that isn't over by then
+        let i = self.end_times.partition_point(|&t| t <= self.clock);
+        let (before, start) = self.event_start(i);
+        let cursor = match self.events.get(i) {
+            Some(ReplayEvent::Ticks { dt, count }) => {
+                let done = ((self.clock - start) / dt).floor() as usize;
+                before + done.min(count.saturating_sub(1) as usize)
+            }
+            _ => before,
+        };
+        self.seek_steps(pmesh, cursor)
+    }
+
+    /// Puts the bodies where they were after `cursor` steps
+    pub fn seek(&mut self, pmesh: &mut PhysicsMesh, cursor: usize) -> anyhow::Result<()> {
+        self.seek_steps(pmesh, cursor)?;
+        self.clock = pmesh.time;
+        Ok(())
+    }
+
+    fn seek_steps(&mut self, pmesh: &mut PhysicsMesh, cursor: usize) -> anyhow::Result<()> {
+        let cursor = cursor.min(self.len());
+        let keyframe = (cursor / KEYFRAME_INTERVAL).min(self.keyframes.len() - 1);
+        let keyframe_cursor = keyframe * KEYFRAME_INTERVAL;
+        if cursor < self.cursor || keyframe_cursor > self.cursor {
+            self.keyframes[keyframe].restore(pmesh);
+            self.cursor = keyframe_cursor;
+        }
+
+        while self.cursor < cursor {
+            let i = self.ends.partition_point(|&end| end <= self.cursor);
+            match &self.events[i] {
+                ReplayEvent::Ticks { dt, .. } => pmesh.compute(*dt),
+                ReplayEvent::Intervene(intervention) => pmesh.intervene(intervention)?,
+            }
+            self.cursor += 1;
+            if self.cursor.is_multiple_of(KEYFRAME_INTERVAL)
+                && self.keyframes.len() == self.cursor / KEYFRAME_INTERVAL
+            {
+                self.keyframes.push(Keyframe::take(pmesh));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs a whole replay without a window, to check where it ends up
+pub fn play_to_end(replay: &Replay) -> anyhow::Result<PhysicsMesh> {
+    replay.config.validate()?;
+    let mut pmesh = replay
+        .config
+        .physics
+        .as_physics_mesh(replay.config.global.const_g, replay.config.bodies.clone())?;
+    let mut player = ReplayPlayer::new(replay.events.clone(), &pmesh);
+    player.seek(&mut pmesh, player.len())?;
+    Ok(pmesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use three_d::{vec3, Srgba};
+
+    use super::*;
+
+    fn pmesh() -> PhysicsMesh {
+        let mut pmesh = PhysicsMesh::default();
+        for (name, x, vy) in [("a", -1.0, -0.001), ("b", 1.0, 0.001), ("c", 0.0, 0.0)] {
+            let mut gbody = GBody::new(name.to_owned(), Srgba::WHITE, 0.1, 1e3);
+            gbody.set_motion(vec3(x, 0.0, 0.0), vec3(0.0, vy, 0.0));
+            pmesh.add(gbody);
+        }
+        pmesh
+    }
+
+    fn events() -> Vec<ReplayEvent> {
+        vec![
+            ReplayEvent::Ticks {
+                dt: 1.0,
+                count: 1000,
+            },
+            ReplayEvent::Intervene(Intervention::Remove {
+                body: "c".to_owned(),
+            }),
+            ReplayEvent::Ticks {
+                dt: 0.5,
+                count: 600,
+            },
+        ]
+    }
+
+    #[test]
+    fn seeks_by_time_across_runs_of_ticks() {
+        let mut pmesh = pmesh();
+        let mut player = ReplayPlayer::new(events(), &pmesh);
+        assert_eq!(player.len(), 1601);
+        assert_eq!(player.time_range(), (0.0, 1300.0));
+
+        for (time, cursor, bodies) in [
+            (999.9, 999, 3),
+            // the intervention happens at the same time as the tick before it
+            (1000.0, 1001, 2),
+            (1000.4, 1001, 2),
+            (1150.0, 1301, 2),
+            (10.0, 10, 3),
+            (2000.0, 1601, 2),
+        ] {
+            player.seek_time(&mut pmesh, time).unwrap();
+            assert_eq!(player.cursor(), cursor, "at {time}");
+            assert_eq!(pmesh.components.len(), bodies, "at {time}");
+        }
+    }
+
+    #[test]
+    fn going_back_redoes_the_same_states() {
+        let mut straight = pmesh();
+        let mut player = ReplayPlayer::new(events(), &straight);
+        player.seek(&mut straight, 1300).unwrap();
+
+        let mut seeking = pmesh();
+        let mut player = ReplayPlayer::new(events(), &seeking);
+        for cursor in [1500, 700, 1601, 3, 1300] {
+            player.seek(&mut seeking, cursor).unwrap();
+        }
+        assert_eq!(seeking.time.to_bits(), straight.time.to_bits());
+        for (a, b) in seeking.components.iter().zip(&straight.components) {
+            assert_eq!(a.pos, b.pos);
+            assert_eq!(a.vel, b.vel);
+        }
+    }
+
+    #[test]
+    fn playing_back_is_bit_identical_to_the_run() {
+        let mut config = crate::config::Preset::Example.config();
+        config.physics.integrator = crate::config::IntegratorKind::DormandPrince;
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", std::process::id()));
+
+        let mut live = config
+            .physics
+            .as_physics_mesh(config.global.const_g, config.bodies.clone())
+            .unwrap();
+        let mut writer = ReplayWriter::create(&path, &config).unwrap();
+        for i in 0..3000 {
+            // frames don't always fit whole ticks
+            let dt = if i % 700 < 350 { 1.0 } else { 0.37 };
+            live.compute(dt);
+            writer.tick(dt);
+            if i == 1000 {
+                let moon = Intervention::SetMotion {
+                    body: "moon".to_owned(),
+                    pos: [9.0, 0.0, 0.0],
+                    vel: [0.0, -0.0006, 0.0],
+                };
+                writer.intervene(&mut live, moon).unwrap();
+            }
+            if i == 2000 {
+                let earth = Intervention::Remove {
+                    body: "earth".to_owned(),
+                };
+                writer.intervene(&mut live, earth).unwrap();
+            }
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        let replay = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let played = play_to_end(&replay.unwrap()).unwrap();
+        assert_eq!(played.time.to_bits(), live.time.to_bits());
+        assert_eq!(played.components.len(), live.components.len());
+        for (a, b) in played.components.iter().zip(&live.components) {
+            let bits = |v: Vector3<f64>| [v.x, v.y, v.z].map(f64::to_bits);
+            assert_eq!(bits(a.pos), bits(b.pos), "{}", a.name);
+            assert_eq!(bits(a.vel), bits(b.vel), "{}", a.name);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use three_d::{Event, Key};
+
+use crate::config::Config;
+
+const SAVE_KEY: Key = Key::F5;
+
+/// Whether the key saving a snapshot was pressed this frame
+pub fn requested(events: &mut [Event]) -> bool {
+    let mut requested = false;
+    for event in events {
+        if let Event::KeyPress {
+            kind: SAVE_KEY,
+            handled,
+            ..
+        } = event
+        {
+            if !*handled {
+                requested = true;
+                *handled = true;
+            }
+        }
+    }
+    requested
+}
+
+/// Writes `snapshot` to the working directory, or has the browser download
+/// it on the web, returns the file's name. It's a plain config, load it as
+/// any other to resume from it. `time_unit` is what the run it's from
+/// counted time in, for the name.
+pub fn save(snapshot: &Config, time_unit: Option<&str>) -> anyhow::Result<String> {
+    let unit: String = time_unit
+        .unwrap_or_default()
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect();
+    let name = format!("snapshot-{:.0}{unit}.toml", snapshot.physics.start_time);
+    let contents = toml::to_string_pretty(snapshot)?;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    std::fs::write(&name, contents)?;
+    #[cfg(target_arch = "wasm32")]
+    crate::wasm::download(&name, &contents)
+        .map_err(|err| anyhow::anyhow!("couldn't download {name}: {err:?}"))?;
+
+    Ok(name)
+}
@@ -5,7 +5,7 @@ use std::{cell::RefCell, rc::Rc};
 use {
     wasm_bindgen::prelude::*,
     wasm_bindgen_futures::spawn_local,
-    web_sys::{window, File, FileReader, HtmlInputElement},
+    web_sys::{window, Blob, File, FileReader, HtmlAnchorElement, HtmlInputElement, Url},
 };
 
 #[wasm_bindgen]
@@ -59,6 +59,7 @@ pub async fn main() -> Result<(), JsValue> {
 pub fn wasm_run_from_conf(file: File) {
     web_sys::console::log_1(&file.name().into());
     web_sys::console::log_1(&format!("{:?}", file).into());
+    let file_name = file.name();
 
     let reader = FileReader::new().unwrap();
 
@@ -67,8 +68,13 @@ pub fn wasm_run_from_conf(file: File) {
         if let Some(target) = evt.target() {
             let reader = target.dyn_into::<FileReader>().unwrap();
             if let Some(result) = reader.result().unwrap().as_string() {
-                // Log the file contents
-                let config: config::Config = toml::from_str(&result).expect("invalid config");
+                let config = match config::Config::from_toml(&result, &file_name) {
+                    Ok(config) => config,
+                    Err(err) => {
+                        web_sys::console::error_1(&err.to_string().into());
+                        return;
+                    }
+                };
                 spawn_local(async {
                     crate::run(config).await.expect("Error running simulation");
                 });
@@ -81,3 +87,23 @@ pub fn wasm_run_from_conf(file: File) {
 
     reader.read_as_text(&file).unwrap();
 }
+
+/// Has the browser download `contents` as a file called `name`
+pub fn download(name: &str, contents: &str) -> Result<(), JsValue> {
+    let document = window()
+        .and_then(|window| window.document())
+        .ok_or("No document on window")?;
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let blob = Blob::new_with_str_sequence(&parts)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let anchor = document
+        .create_element("a")?
+        .dyn_into::<HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+
+    Url::revoke_object_url(&url)
+}